@@ -0,0 +1,240 @@
+use crate::collections::consts::{
+    PLANET_RADIUS, PLANET_TERRAIN_AMP, PLANET_TEXTURE_HEIGHT, PLANET_TEXTURE_WIDTH, WATER_LEVEL,
+};
+
+const NO_SEED: u32 = u32::MAX;
+
+// 1+JFA: a leading single texel step removes most of the flood's errors
+pub(crate) fn jump_flood_steps() -> Vec<u32> {
+    jump_flood_steps_for(PLANET_TEXTURE_WIDTH, PLANET_TEXTURE_HEIGHT)
+}
+
+// Halves from half the larger side, odd sizes round the first step down
+fn jump_flood_steps_for(width: u32, height: u32) -> Vec<u32> {
+    let mut steps = vec![1];
+    let mut step = width.max(height) / 2;
+
+    while step > 0 {
+        steps.push(step);
+        step /= 2;
+    }
+
+    steps
+}
+
+fn is_land(elevation: f32) -> bool {
+    PLANET_RADIUS - elevation * PLANET_TERRAIN_AMP >= WATER_LEVEL
+}
+
+// Texel grid of the flood, the planet texture outside of tests
+#[derive(Clone, Copy)]
+struct Grid {
+    width: i32,
+    height: i32,
+}
+
+const PLANET_GRID: Grid = Grid {
+    width: PLANET_TEXTURE_WIDTH as i32,
+    height: PLANET_TEXTURE_HEIGHT as i32,
+};
+
+impl Grid {
+    fn contains(self, x: i32, y: i32) -> bool {
+        x >= 0 && x < self.width && y >= 0 && y < self.height
+    }
+
+    fn index(self, x: i32, y: i32) -> usize {
+        y as usize * self.width as usize + x as usize
+    }
+
+    // The terrain texture doesn't tile, the planet sampler clamps to its edges
+    fn clamped(self, x: i32, y: i32) -> usize {
+        self.index(x.clamp(0, self.width - 1), y.clamp(0, self.height - 1))
+    }
+
+    fn coord(self, idx: u32) -> (i32, i32) {
+        (
+            (idx % self.width as u32) as i32,
+            (idx / self.width as u32) as i32,
+        )
+    }
+}
+
+fn delta(a: (i32, i32), b: (i32, i32)) -> (i32, i32) {
+    (b.0 - a.0, b.1 - a.1)
+}
+
+// CPU version of the jump flood in wave_directions.wgsl, `map` is the
+// flattened rgba planet texture. Returns a normalised direction towards
+// the nearest coast for every water texel and zero on land.
+pub(crate) fn coast_wave_directions(map: &[f32]) -> Vec<(f32, f32)> {
+    flood_wave_directions(map, PLANET_GRID)
+}
+
+fn flood_wave_directions(map: &[f32], grid: Grid) -> Vec<(f32, f32)> {
+    let Grid { width, height } = grid;
+    let land = map
+        .chunks_exact(4)
+        .map(|c| is_land(c[0]))
+        .collect::<Vec<bool>>();
+
+    let mut seeds = vec![NO_SEED; land.len()];
+
+    for y in 0..height {
+        for x in 0..width {
+            let idx = grid.index(x, y);
+            let coast = land[idx]
+                && (!land[grid.clamped(x + 1, y)]
+                    || !land[grid.clamped(x - 1, y)]
+                    || !land[grid.clamped(x, y + 1)]
+                    || !land[grid.clamped(x, y - 1)]);
+
+            if coast {
+                seeds[idx] = idx as u32;
+            }
+        }
+    }
+
+    let mut next = vec![NO_SEED; land.len()];
+
+    for step in jump_flood_steps_for(width as u32, height as u32) {
+        let step = step as i32;
+
+        for y in 0..height {
+            for x in 0..width {
+                let mut best = NO_SEED;
+                let mut best_dist = i32::MAX;
+
+                for oy in -1..=1 {
+                    for ox in -1..=1 {
+                        // Offsets past an edge have nothing to offer, seeds never cross it
+                        let (qx, qy) = (x + ox * step, y + oy * step);
+                        if !grid.contains(qx, qy) {
+                            continue;
+                        }
+
+                        let seed = seeds[grid.index(qx, qy)];
+                        if seed == NO_SEED {
+                            continue;
+                        }
+
+                        let (dx, dy) = delta((x, y), grid.coord(seed));
+                        let dist = dx * dx + dy * dy;
+                        if dist < best_dist {
+                            best = seed;
+                            best_dist = dist;
+                        }
+                    }
+                }
+
+                next[grid.index(x, y)] = best;
+            }
+        }
+
+        std::mem::swap(&mut seeds, &mut next);
+    }
+
+    seeds
+        .iter()
+        .enumerate()
+        .map(|(idx, seed)| {
+            if *seed == NO_SEED || land[idx] {
+                return (0.0, 0.0);
+            }
+
+            let (dx, dy) = delta(grid.coord(idx as u32), grid.coord(*seed));
+            let dir = nalgebra::Vector2::new(dx as f32, dy as f32);
+            match dir.try_normalize(0.0) {
+                Some(n) => (n.x, n.y),
+                None => (0.0, 0.0),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{delta, flood_wave_directions, jump_flood_steps_for, Grid};
+
+    // Radius 60.0 and 40.0, either side of WATER_LEVEL
+    const LAND: f32 = -1.0;
+    const WATER: f32 = 1.0;
+
+    const GRID: Grid = Grid {
+        width: 16,
+        height: 8,
+    };
+
+    fn map(land: impl Fn(i32, i32) -> bool) -> Vec<f32> {
+        let mut map = Vec::new();
+        for y in 0..GRID.height {
+            for x in 0..GRID.width {
+                let elevation = if land(x, y) { LAND } else { WATER };
+                map.extend_from_slice(&[elevation, 0.0, 0.0, 0.0]);
+            }
+        }
+        map
+    }
+
+    #[test]
+    fn delta_does_not_wrap_across_the_edges() {
+        assert_eq!(delta((1, 0), (14, 0)), (13, 0));
+        assert_eq!(delta((14, 0), (1, 0)), (-13, 0));
+        assert_eq!(delta((0, 7), (0, 1)), (0, -6));
+    }
+
+    #[test]
+    fn clamped_reads_the_edge_texel() {
+        assert_eq!(GRID.clamped(-1, 0), GRID.index(0, 0));
+        assert_eq!(GRID.clamped(16, 3), GRID.index(15, 3));
+        assert_eq!(GRID.clamped(4, -1), GRID.index(4, 0));
+        assert_eq!(GRID.clamped(3, 9), GRID.index(3, 7));
+        assert_eq!(GRID.coord(GRID.index(13, 1) as u32), (13, 1));
+    }
+
+    #[test]
+    fn water_never_points_across_the_edge() {
+        // Land only in column 0, every water texel points back left at it, even the
+        // last column which would be one texel away if the texture tiled
+        let directions = flood_wave_directions(&map(|x, _| x == 0), GRID);
+
+        for y in 0..GRID.height {
+            for x in [1, 5, 12, 15] {
+                assert_eq!(directions[GRID.index(x, y)], (-1.0, 0.0));
+            }
+            assert_eq!(directions[GRID.index(0, y)], (0.0, 0.0));
+        }
+    }
+
+    #[test]
+    fn water_points_at_the_nearest_coast() {
+        let directions = flood_wave_directions(&map(|x, _| x == 0 || x == 10), GRID);
+
+        for y in 0..GRID.height {
+            assert_eq!(directions[GRID.index(3, y)], (-1.0, 0.0));
+            assert_eq!(directions[GRID.index(7, y)], (1.0, 0.0));
+            assert_eq!(directions[GRID.index(12, y)], (-1.0, 0.0));
+            assert_eq!(directions[GRID.index(15, y)], (-1.0, 0.0));
+        }
+    }
+
+    #[test]
+    fn all_land_has_no_directions() {
+        let directions = flood_wave_directions(&map(|_, _| true), GRID);
+        assert!(directions.iter().all(|d| *d == (0.0, 0.0)));
+    }
+
+    #[test]
+    fn all_water_has_no_coast_to_point_at() {
+        let directions = flood_wave_directions(&map(|_, _| false), GRID);
+        assert!(directions.iter().all(|d| *d == (0.0, 0.0)));
+    }
+
+    #[test]
+    fn steps_halve_from_half_the_larger_side() {
+        assert_eq!(jump_flood_steps_for(16, 8), vec![1, 8, 4, 2, 1]);
+        assert_eq!(jump_flood_steps_for(100, 30), vec![1, 50, 25, 12, 6, 3, 1]);
+        assert_eq!(jump_flood_steps_for(7, 7), vec![1, 3, 1]);
+        assert_eq!(jump_flood_steps_for(1, 1), vec![1]);
+    }
+}
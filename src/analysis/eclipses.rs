@@ -0,0 +1,152 @@
+use nalgebra::Vector3;
+
+use crate::collections::{
+    consts::{
+        MOON_ORBIT_INCLINATION, MOON_ORBIT_RADIUS, MOON_ORBIT_SPEED, MOON_RADIUS, PLANET_RADIUS,
+    },
+    structs::LightParams,
+};
+
+// Seconds between orbit samples, short enough to catch grazing eclipses
+const TIME_STEP: f32 = 0.01;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum EclipseKind {
+    // The moon's shadow falls on the planet
+    Solar,
+    // The moon passes through the planet's shadow
+    Lunar,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Eclipse {
+    pub(crate) kind: EclipseKind,
+    // Times in seconds of app time, the same clock as the time uniform
+    pub(crate) start: f32,
+    pub(crate) peak: f32,
+    pub(crate) end: f32,
+    // 1.0 when the shadow axis passes through the centre of the shadowed body
+    pub(crate) magnitude: f32,
+}
+
+// Same orbit as get_moon_position in frag.wgsl, the moon is centred on the negated offset
+pub(crate) fn moon_center(time: f32) -> Vector3<f32> {
+    let angle = time * MOON_ORBIT_SPEED;
+    let x = MOON_ORBIT_RADIUS * angle.cos();
+    let z = MOON_ORBIT_RADIUS * angle.sin();
+    let y = MOON_ORBIT_INCLINATION * angle.sin() * 3.0;
+
+    -Vector3::new(x, y - MOON_ORBIT_INCLINATION - x * 0.1, z)
+}
+
+// Same as get_light_pos in frag.wgsl
+pub(crate) fn sun_position(light: &LightParams, time: f32) -> Vector3<f32> {
+    let azimuth = light.sun_azimuth + light.orbit_speed * time;
+    let elevation = light.sun_elevation;
+
+    Vector3::new(
+        elevation.cos() * azimuth.sin(),
+        elevation.sin(),
+        -elevation.cos() * azimuth.cos(),
+    ) * light.sun_distance
+}
+
+// How deep `target` sits in the penumbra cast by `occluder`, 0.0 outside it
+fn shadow_depth(
+    sun: &Vector3<f32>,
+    sun_angular_radius: f32,
+    occluder: &Vector3<f32>,
+    occluder_radius: f32,
+    target: &Vector3<f32>,
+    target_radius: f32,
+) -> f32 {
+    let axis = (occluder - sun).normalize();
+    let to_target = target - occluder;
+    let along = to_target.dot(&axis);
+
+    // The target has to be behind the occluder
+    if along <= 0.0 {
+        return 0.0;
+    }
+
+    let miss = (to_target - axis * along).norm();
+    let penumbra = occluder_radius + along * sun_angular_radius.tan();
+    let reach = penumbra + target_radius;
+
+    if miss >= reach {
+        0.0
+    } else {
+        1.0 - miss / reach
+    }
+}
+
+// Scans [from, from + duration] and returns every eclipse overlapping it
+pub(crate) fn predict_eclipses(light: &LightParams, from: f32, duration: f32) -> Vec<Eclipse> {
+    let planet = Vector3::zeros();
+    let steps = (duration / TIME_STEP).ceil() as usize;
+    let mut eclipses = Vec::new();
+    let mut current: [Option<Eclipse>; 2] = [None, None];
+
+    for i in 0..=steps {
+        let time = from + i as f32 * TIME_STEP;
+        let sun = sun_position(light, time);
+        let moon = moon_center(time);
+
+        let depths = [
+            (
+                EclipseKind::Solar,
+                shadow_depth(
+                    &sun,
+                    light.angular_radius,
+                    &moon,
+                    MOON_RADIUS,
+                    &planet,
+                    PLANET_RADIUS,
+                ),
+            ),
+            (
+                EclipseKind::Lunar,
+                shadow_depth(
+                    &sun,
+                    light.angular_radius,
+                    &planet,
+                    PLANET_RADIUS,
+                    &moon,
+                    MOON_RADIUS,
+                ),
+            ),
+        ];
+
+        for (slot, (kind, depth)) in current.iter_mut().zip(depths) {
+            match slot {
+                Some(eclipse) if depth > 0.0 => {
+                    eclipse.end = time;
+                    if depth > eclipse.magnitude {
+                        eclipse.magnitude = depth;
+                        eclipse.peak = time;
+                    }
+                }
+                Some(eclipse) => {
+                    eclipses.push(*eclipse);
+                    *slot = None;
+                }
+                None if depth > 0.0 => {
+                    *slot = Some(Eclipse {
+                        kind,
+                        start: time,
+                        peak: time,
+                        end: time,
+                        magnitude: depth,
+                    });
+                }
+                None => {}
+            }
+        }
+    }
+
+    // Report eclipses still running at the end of the window
+    eclipses.extend(current.into_iter().flatten());
+    eclipses.sort_by(|a, b| a.start.total_cmp(&b.start));
+
+    eclipses
+}
@@ -0,0 +1,58 @@
+use nalgebra::Vector3;
+
+use crate::collections::consts::{
+    PLANET_RADIUS, PLANET_TERRAIN_AMP, PLANET_TEXTURE_HEIGHT, PLANET_TEXTURE_WIDTH, WATER_LEVEL,
+};
+
+// Heights from the red channel of the planet texture, looked up the same way
+// tex_triplanar_mapping in frag.wgsl does
+#[derive(Debug)]
+pub(crate) struct HeightField {
+    width: usize,
+    height: usize,
+    heights: Vec<f32>,
+}
+
+impl HeightField {
+    // `map` is the flattened rgba planet texture
+    pub(crate) fn from_height_map(map: &[f32]) -> Self {
+        Self {
+            width: PLANET_TEXTURE_WIDTH as usize,
+            height: PLANET_TEXTURE_HEIGHT as usize,
+            heights: map.chunks_exact(4).map(|c| c[0]).collect(),
+        }
+    }
+
+    // Bilinear with clamped edges, like the planet sampler
+    fn sample(&self, u: f32, v: f32) -> f32 {
+        let x = (u * self.width as f32 - 0.5).clamp(0.0, (self.width - 1) as f32);
+        let y = (v * self.height as f32 - 0.5).clamp(0.0, (self.height - 1) as f32);
+        let x0 = x.floor() as usize;
+        let y0 = y.floor() as usize;
+        let x1 = (x0 + 1).min(self.width - 1);
+        let y1 = (y0 + 1).min(self.height - 1);
+        let fx = x - x0 as f32;
+        let fy = y - y0 as f32;
+
+        let h = |x: usize, y: usize| self.heights[y * self.width + x];
+        let top = h(x0, y0) * (1.0 - fx) + h(x1, y0) * fx;
+        let bottom = h(x0, y1) * (1.0 - fx) + h(x1, y1) * fx;
+        top * (1.0 - fy) + bottom * fy
+    }
+
+    // Distance from the planet centre to the ground or the sea below `local`,
+    // a direction in the planet's rotating frame
+    pub(crate) fn ground_radius(&self, local: &Vector3<f32>) -> f32 {
+        let p = local.normalize();
+
+        // Same plane offsets and sharpened blend weights as the shader
+        let mut n = p.abs().map(|x| x.powi(6));
+        n /= n.x + n.y + n.z;
+
+        let tx = self.sample(p.x * 0.5 + 0.47, p.y * 0.5 + 0.47) * n.z
+            + self.sample(p.x * 0.5 + 0.51, p.z * 0.5 + 0.51) * n.y
+            + self.sample(p.y * 0.5 + 0.55, p.z * 0.5 + 0.55) * n.x;
+
+        f32::max(PLANET_RADIUS - tx * PLANET_TERRAIN_AMP, WATER_LEVEL)
+    }
+}
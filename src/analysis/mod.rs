@@ -0,0 +1,4 @@
+pub(crate) mod coast_field;
+pub(crate) mod eclipses;
+pub(crate) mod height_field;
+pub(crate) mod terrain_report;
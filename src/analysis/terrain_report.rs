@@ -0,0 +1,389 @@
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::collections::consts::{
+    ICE_LEVEL, PLANET_RADIUS, PLANET_TERRAIN_AMP, PLANET_TEXTURE_HEIGHT, PLANET_TEXTURE_WIDTH,
+    WATER_LEVEL,
+};
+
+const HISTOGRAM_BINS: usize = 64;
+const PERCENTILES: [f32; 9] = [1.0, 5.0, 10.0, 25.0, 50.0, 75.0, 90.0, 95.0, 99.0];
+
+#[derive(Debug, Serialize)]
+pub(crate) struct HistogramBin {
+    pub(crate) lower: f32,
+    pub(crate) upper: f32,
+    pub(crate) count: u32,
+    pub(crate) fraction: f32,
+}
+
+// Fraction of the surface at or above an elevation
+#[derive(Debug, Serialize)]
+pub(crate) struct HypsometricPoint {
+    pub(crate) elevation: f32,
+    pub(crate) area_above: f32,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct Percentile {
+    pub(crate) percentile: f32,
+    pub(crate) elevation: f32,
+}
+
+// Elevations are measured from WATER_LEVEL, negative values are seabed
+#[derive(Debug, Serialize)]
+pub(crate) struct TerrainReport {
+    pub(crate) texel_count: usize,
+    pub(crate) min_elevation: f32,
+    pub(crate) max_elevation: f32,
+    pub(crate) mean_elevation: f32,
+    pub(crate) percentiles: Vec<Percentile>,
+    pub(crate) land_fraction: f32,
+    pub(crate) water_fraction: f32,
+    pub(crate) land_water_ratio: f32,
+    // Altitude ice only, polar caps depend on the view position not the heightmap
+    pub(crate) ice_fraction: f32,
+    pub(crate) mean_slope_degrees: f32,
+    pub(crate) mean_land_slope_degrees: f32,
+    pub(crate) histogram: Vec<HistogramBin>,
+    pub(crate) hypsometric_curve: Vec<HypsometricPoint>,
+}
+
+fn elevation(tx: f32) -> f32 {
+    PLANET_RADIUS - tx * PLANET_TERRAIN_AMP - WATER_LEVEL
+}
+
+impl TerrainReport {
+    // `map` is the flattened rgba planet texture
+    pub(crate) fn from_height_map(map: &[f32]) -> Self {
+        Self::from_sized_height_map(
+            map,
+            PLANET_TEXTURE_WIDTH as usize,
+            PLANET_TEXTURE_HEIGHT as usize,
+        )
+    }
+
+    fn from_sized_height_map(map: &[f32], width: usize, height: usize) -> Self {
+        let elevations = map
+            .chunks_exact(4)
+            .map(|c| elevation(c[0]))
+            .collect::<Vec<f32>>();
+        let texel_count = elevations.len();
+        let count_f = texel_count as f32;
+
+        let mut sorted = elevations.clone();
+        sorted.sort_unstable_by(f32::total_cmp);
+
+        let min_elevation = sorted[0];
+        let max_elevation = sorted[texel_count - 1];
+        let mean_elevation =
+            (elevations.iter().map(|e| *e as f64).sum::<f64>() / texel_count as f64) as f32;
+
+        let percentiles = PERCENTILES
+            .iter()
+            .map(|p| {
+                let idx = ((p / 100.0) * (texel_count - 1) as f32).round() as usize;
+                Percentile {
+                    percentile: *p,
+                    elevation: sorted[idx],
+                }
+            })
+            .collect();
+
+        let land = elevations.iter().filter(|e| **e >= 0.0).count();
+        let ice_elevation = ICE_LEVEL - WATER_LEVEL;
+        let ice = elevations.iter().filter(|e| **e >= ice_elevation).count();
+
+        // The triplanar projection spans the texture across the planet diameter
+        let texel_size = 2.0 * PLANET_RADIUS / width as f32;
+        let mut slope_sum = 0.0f64;
+        let mut land_slope_sum = 0.0f64;
+
+        for y in 0..height {
+            for x in 0..width {
+                // Central differences, one-sided on the edges since the texture doesn't
+                // tile and the planet sampler clamps there
+                let (x0, x1) = (x.saturating_sub(1), (x + 1).min(width - 1));
+                let (y0, y1) = (y.saturating_sub(1), (y + 1).min(height - 1));
+                let at = |x: usize, y: usize| elevations[y * width + x];
+                let dx = (at(x1, y) - at(x0, y)) / ((x1 - x0).max(1) as f32 * texel_size);
+                let dy = (at(x, y1) - at(x, y0)) / ((y1 - y0).max(1) as f32 * texel_size);
+                let gradient = (dx * dx + dy * dy).sqrt();
+                let slope = gradient.atan().to_degrees() as f64;
+
+                slope_sum += slope;
+                if elevations[y * width + x] >= 0.0 {
+                    land_slope_sum += slope;
+                }
+            }
+        }
+
+        let bin_size = ((max_elevation - min_elevation) / HISTOGRAM_BINS as f32).max(f32::EPSILON);
+        let mut counts = vec![0u32; HISTOGRAM_BINS];
+        for e in elevations.iter() {
+            let bin = (((e - min_elevation) / bin_size) as usize).min(HISTOGRAM_BINS - 1);
+            counts[bin] += 1;
+        }
+
+        let histogram = counts
+            .iter()
+            .enumerate()
+            .map(|(i, count)| HistogramBin {
+                lower: min_elevation + i as f32 * bin_size,
+                upper: min_elevation + (i + 1) as f32 * bin_size,
+                count: *count,
+                fraction: *count as f32 / count_f,
+            })
+            .collect::<Vec<HistogramBin>>();
+
+        let mut above = texel_count as u32;
+        let mut hypsometric_curve = Vec::with_capacity(HISTOGRAM_BINS + 1);
+        for bin in histogram.iter() {
+            hypsometric_curve.push(HypsometricPoint {
+                elevation: bin.lower,
+                area_above: above as f32 / count_f,
+            });
+            above -= bin.count;
+        }
+        hypsometric_curve.push(HypsometricPoint {
+            elevation: max_elevation,
+            area_above: 0.0,
+        });
+
+        Self {
+            texel_count,
+            min_elevation,
+            max_elevation,
+            mean_elevation,
+            percentiles,
+            land_fraction: land as f32 / count_f,
+            water_fraction: (texel_count - land) as f32 / count_f,
+            land_water_ratio: land as f32 / (texel_count - land).max(1) as f32,
+            ice_fraction: ice as f32 / count_f,
+            mean_slope_degrees: (slope_sum / texel_count as f64) as f32,
+            mean_land_slope_degrees: (land_slope_sum / land.max(1) as f64) as f32,
+            histogram,
+            hypsometric_curve,
+        }
+    }
+
+    pub(crate) fn to_csv(&self) -> String {
+        let mut csv = String::from("metric,value\n");
+        let _ = writeln!(csv, "texel_count,{}", self.texel_count);
+        let _ = writeln!(csv, "min_elevation,{}", self.min_elevation);
+        let _ = writeln!(csv, "max_elevation,{}", self.max_elevation);
+        let _ = writeln!(csv, "mean_elevation,{}", self.mean_elevation);
+        for p in self.percentiles.iter() {
+            let _ = writeln!(csv, "p{},{}", p.percentile, p.elevation);
+        }
+        let _ = writeln!(csv, "land_fraction,{}", self.land_fraction);
+        let _ = writeln!(csv, "water_fraction,{}", self.water_fraction);
+        let _ = writeln!(csv, "land_water_ratio,{}", self.land_water_ratio);
+        let _ = writeln!(csv, "ice_fraction,{}", self.ice_fraction);
+        let _ = writeln!(csv, "mean_slope_degrees,{}", self.mean_slope_degrees);
+        let _ = writeln!(
+            csv,
+            "mean_land_slope_degrees,{}",
+            self.mean_land_slope_degrees
+        );
+
+        // Histogram and hypsometric curve share the bin edges
+        csv.push_str("\nbin_lower,bin_upper,count,fraction,area_above_lower\n");
+        for (bin, hyps) in self.histogram.iter().zip(self.hypsometric_curve.iter()) {
+            let _ = writeln!(
+                csv,
+                "{},{},{},{},{}",
+                bin.lower, bin.upper, bin.count, bin.fraction, hyps.area_above
+            );
+        }
+
+        csv
+    }
+
+    // Writes terrain_report_<unix time>.json and .csv into `dir`
+    pub(crate) fn write_files(&self, dir: &Path) -> std::io::Result<(PathBuf, PathBuf)> {
+        std::fs::create_dir_all(dir)?;
+
+        let stamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        let json_path = dir.join(format!("terrain_report_{stamp}.json"));
+        let csv_path = dir.join(format!("terrain_report_{stamp}.csv"));
+
+        let json = serde_json::to_string_pretty(self).map_err(std::io::Error::other)?;
+        std::fs::write(&json_path, json)?;
+        std::fs::write(&csv_path, self.to_csv())?;
+
+        Ok((json_path, csv_path))
+    }
+}
+
+impl std::fmt::Display for TerrainReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "TerrainReport ({} texels, elevation from water level)",
+            self.texel_count
+        )?;
+        writeln!(
+            f,
+            "  elevation: min {:.3}, max {:.3}, mean {:.3}",
+            self.min_elevation, self.max_elevation, self.mean_elevation
+        )?;
+        for p in self.percentiles.iter() {
+            writeln!(f, "  p{:<4} {:>8.3}", p.percentile, p.elevation)?;
+        }
+        writeln!(
+            f,
+            "  land {:.1}%, water {:.1}%, land/water ratio {:.3}",
+            self.land_fraction * 100.0,
+            self.water_fraction * 100.0,
+            self.land_water_ratio
+        )?;
+        writeln!(f, "  ice {:.2}%", self.ice_fraction * 100.0)?;
+        write!(
+            f,
+            "  mean slope {:.2} deg, land {:.2} deg",
+            self.mean_slope_degrees, self.mean_land_slope_degrees
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TerrainReport, HISTOGRAM_BINS, PERCENTILES};
+    use crate::collections::consts::{PLANET_RADIUS, PLANET_TERRAIN_AMP, WATER_LEVEL};
+
+    const WIDTH: usize = 10;
+    const HEIGHT: usize = 10;
+
+    fn report(
+        width: usize,
+        height: usize,
+        elevation: impl Fn(usize, usize) -> f32,
+    ) -> TerrainReport {
+        let map: Vec<f32> = (0..width * height)
+            .flat_map(|i| {
+                let e = elevation(i % width, i / width);
+                let tx = (PLANET_RADIUS - WATER_LEVEL - e) / PLANET_TERRAIN_AMP;
+                [tx, 0.0, 0.0, 0.0]
+            })
+            .collect();
+
+        TerrainReport::from_sized_height_map(&map, width, height)
+    }
+
+    // Elevations -49.5, -48.5 .. 49.5 in texel order, none of them on the water line
+    fn ramp_report() -> TerrainReport {
+        report(WIDTH, HEIGHT, |x, y| (y * WIDTH + x) as f32 - 49.5)
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-3, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn elevation_range_and_percentiles() {
+        let report = ramp_report();
+
+        assert_eq!(report.texel_count, 100);
+        assert_close(report.min_elevation, -49.5);
+        assert_close(report.max_elevation, 49.5);
+        assert_close(report.mean_elevation, 0.0);
+
+        // Nearest rank over 99 gaps: p1 -> 1, p25 -> 25, p50 -> 50, p99 -> 98
+        let at = |p: f32| {
+            report
+                .percentiles
+                .iter()
+                .find(|q| q.percentile == p)
+                .unwrap()
+                .elevation
+        };
+        assert_close(at(1.0), -48.5);
+        assert_close(at(25.0), -24.5);
+        assert_close(at(50.0), 0.5);
+        assert_close(at(99.0), 48.5);
+    }
+
+    #[test]
+    fn land_water_and_ice_fractions() {
+        let report = ramp_report();
+
+        assert_close(report.land_fraction, 0.5);
+        assert_close(report.water_fraction, 0.5);
+        assert_close(report.land_water_ratio, 1.0);
+        // 3.5 .. 49.5 are above the ice line at 3.3
+        assert_close(report.ice_fraction, 0.47);
+    }
+
+    #[test]
+    fn histogram_and_hypsometric_curve_cover_every_texel() {
+        let report = ramp_report();
+
+        assert_eq!(report.histogram.len(), HISTOGRAM_BINS);
+        assert_eq!(report.histogram.iter().map(|b| b.count).sum::<u32>(), 100);
+        assert_close(report.histogram[0].lower, -49.5);
+        assert_close(report.histogram[HISTOGRAM_BINS - 1].upper, 49.5);
+
+        let curve = &report.hypsometric_curve;
+        assert_eq!(curve.len(), HISTOGRAM_BINS + 1);
+        assert_close(curve[0].area_above, 1.0);
+        assert_close(curve[HISTOGRAM_BINS].area_above, 0.0);
+        assert!(curve.windows(2).all(|w| w[1].area_above <= w[0].area_above));
+    }
+
+    #[test]
+    fn flat_terrain_has_no_slope() {
+        let report = report(WIDTH, HEIGHT, |_, _| 2.0);
+
+        assert_close(report.mean_slope_degrees, 0.0);
+        assert_close(report.mean_land_slope_degrees, 0.0);
+    }
+
+    #[test]
+    fn slopes_average_over_all_texels_and_over_land() {
+        // Four texels across the diameter are 25.0 apart. Column 0 is under water and
+        // rises a full texel to column 1, a 45 degree one-sided slope on the edge.
+        // Column 1 rises half that per texel across its neighbours, atan(0.5), and
+        // columns 2 and 3 are flat, the edge not wrapping back down to column 0.
+        let texel_size = 2.0 * PLANET_RADIUS / 4.0;
+        let profile = [
+            -10.0,
+            texel_size - 10.0,
+            texel_size - 10.0,
+            texel_size - 10.0,
+        ];
+        let report = report(4, 4, |x, _| profile[x]);
+        let half_rise = 0.5f32.atan().to_degrees();
+
+        assert_close(report.land_fraction, 0.75);
+        assert_close(report.mean_slope_degrees, (45.0 + half_rise) / 4.0);
+        assert_close(report.mean_land_slope_degrees, half_rise / 3.0);
+    }
+
+    #[test]
+    fn csv_has_a_row_per_metric_and_bin() {
+        let csv = ramp_report().to_csv();
+        let (metrics, bins) = csv.split_once("\n\n").unwrap();
+
+        let metric_rows: Vec<&str> = metrics.lines().collect();
+        assert_eq!(metric_rows[0], "metric,value");
+        // texel count, min, max and mean, the percentiles, then six fractions and slopes
+        assert_eq!(metric_rows.len(), 1 + 4 + PERCENTILES.len() + 6);
+        assert!(metric_rows.contains(&"texel_count,100"));
+        assert!(metric_rows.iter().any(|r| r.starts_with("p50,")));
+
+        let bin_rows: Vec<&str> = bins.lines().collect();
+        assert_eq!(
+            bin_rows[0],
+            "bin_lower,bin_upper,count,fraction,area_above_lower"
+        );
+        assert_eq!(bin_rows.len(), 1 + HISTOGRAM_BINS);
+        assert!(bin_rows[1..].iter().all(|r| r.split(',').count() == 5));
+    }
+}
@@ -0,0 +1,162 @@
+use nalgebra::{Quaternion, UnitQuaternion, Vector3};
+
+use crate::{
+    camera::surface_camera::{climb_surface_camera, turn_surface_camera, walk_surface_camera},
+    collections::{
+        consts::{
+            CAMERA_FLY_MIN_SPEED, CAMERA_FLY_SPEED, CAMERA_FREE_FLY, CAMERA_MAX_ORBIT_DISTANCE,
+            CAMERA_MAX_PITCH, CAMERA_MIN_ORBIT_DISTANCE, CAMERA_ORBIT, CAMERA_SURFACE,
+            PLANET_RADIUS,
+        },
+        structs::{CameraParams, ViewParams},
+    },
+};
+
+// Position and orientation in planet space, the identity rotation looks
+// down +z with +y up
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Camera {
+    pub(crate) position: Vector3<f32>,
+    pub(crate) orientation: UnitQuaternion<f32>,
+}
+
+impl Camera {
+    // Looks at the planet centre from `distance` away
+    pub(crate) fn orbit(yaw: f32, pitch: f32, distance: f32) -> Self {
+        let orientation = UnitQuaternion::from_axis_angle(&Vector3::y_axis(), yaw)
+            * UnitQuaternion::from_axis_angle(&Vector3::x_axis(), pitch);
+        let position = orientation * Vector3::new(0.0, 0.0, -distance);
+
+        Self {
+            position,
+            orientation,
+        }
+    }
+
+    pub(crate) fn from_view(vp: &ViewParams) -> Self {
+        if vp.controller == CAMERA_ORBIT {
+            return Self::orbit(vp.orbit_yaw, vp.orbit_pitch, vp.orbit_distance);
+        }
+
+        Self {
+            position: Vector3::new(vp.position_x, vp.position_y, vp.position_z),
+            orientation: UnitQuaternion::from_quaternion(Quaternion::new(
+                vp.rotation_w,
+                vp.rotation_x,
+                vp.rotation_y,
+                vp.rotation_z,
+            )),
+        }
+    }
+
+    pub(crate) fn store(&self, vp: &mut ViewParams) {
+        let q = self.orientation.quaternion();
+        vp.position_x = self.position.x;
+        vp.position_y = self.position.y;
+        vp.position_z = self.position.z;
+        vp.rotation_x = q.i;
+        vp.rotation_y = q.j;
+        vp.rotation_z = q.k;
+        vp.rotation_w = q.w;
+    }
+
+    pub(crate) fn params(&self, vp: &ViewParams) -> CameraParams {
+        let q = self.orientation.quaternion();
+        CameraParams {
+            position_x: self.position.x,
+            position_y: self.position.y,
+            position_z: self.position.z,
+            fov_y: vp.fov_degrees.to_radians(),
+            rotation_x: q.i,
+            rotation_y: q.j,
+            rotation_z: q.k,
+            rotation_w: q.w,
+            near: vp.near,
+            far: vp.far,
+        }
+    }
+}
+
+// Keeps the stored pose in step with the orbit, so switching to free-fly
+// starts from where the orbit left the camera
+pub(crate) fn sync_camera(vp: &mut ViewParams) -> Camera {
+    let camera = Camera::from_view(vp);
+    camera.store(vp);
+    camera
+}
+
+pub(crate) fn set_controller(vp: &mut ViewParams, controller: f32) {
+    if vp.controller == controller {
+        return;
+    }
+
+    let camera = sync_camera(vp);
+    vp.controller = controller;
+
+    // Orbit around wherever the free-fly camera was, facing the planet again
+    if controller == CAMERA_ORBIT {
+        let distance = camera.position.norm().max(f32::EPSILON);
+        vp.orbit_distance = distance.clamp(CAMERA_MIN_ORBIT_DISTANCE, CAMERA_MAX_ORBIT_DISTANCE);
+        vp.orbit_pitch = (camera.position.y / distance)
+            .asin()
+            .clamp(-CAMERA_MAX_PITCH, CAMERA_MAX_PITCH);
+        vp.orbit_yaw = f32::atan2(-camera.position.x, -camera.position.z);
+        sync_camera(vp);
+    }
+}
+
+// Positive yaw goes right and positive pitch goes up. Orbit circles the
+// planet, free-fly and surface turn the camera in place
+pub(crate) fn rotate_camera(vp: &mut ViewParams, yaw: f32, pitch: f32) {
+    if vp.controller == CAMERA_SURFACE {
+        turn_surface_camera(vp, yaw, pitch);
+    } else if vp.controller == CAMERA_FREE_FLY {
+        let camera = Camera::from_view(vp);
+        // Yaw around the planet's axis so the horizon doesn't roll
+        let orientation = UnitQuaternion::from_axis_angle(&Vector3::y_axis(), yaw)
+            * camera.orientation
+            * UnitQuaternion::from_axis_angle(&Vector3::x_axis(), -pitch);
+        Camera {
+            orientation,
+            ..camera
+        }
+        .store(vp);
+    } else {
+        vp.orbit_yaw -= yaw;
+        vp.orbit_pitch = (vp.orbit_pitch + pitch).clamp(-CAMERA_MAX_PITCH, CAMERA_MAX_PITCH);
+        sync_camera(vp);
+    }
+}
+
+// Moves the camera toward what it looks at, `amount` is a fraction of the
+// orbit distance or of the free-fly speed. The surface camera descends
+pub(crate) fn dolly_camera(vp: &mut ViewParams, amount: f32) {
+    if vp.controller == CAMERA_SURFACE {
+        climb_surface_camera(vp, -amount);
+    } else if vp.controller == CAMERA_FREE_FLY {
+        fly_camera(vp, Vector3::new(0.0, 0.0, amount));
+    } else {
+        vp.orbit_distance = (vp.orbit_distance * (1.0 - amount))
+            .clamp(CAMERA_MIN_ORBIT_DISTANCE, CAMERA_MAX_ORBIT_DISTANCE);
+        sync_camera(vp);
+    }
+}
+
+// Sideways and up for free-fly, sideways and forward along the ground for
+// the surface camera
+pub(crate) fn move_camera(vp: &mut ViewParams, right: f32, up: f32) {
+    if vp.controller == CAMERA_SURFACE {
+        walk_surface_camera(vp, right, up);
+    } else if vp.controller == CAMERA_FREE_FLY {
+        fly_camera(vp, Vector3::new(right, up, 0.0));
+    }
+}
+
+// Free-fly movement in camera space, slows down close to the surface
+fn fly_camera(vp: &mut ViewParams, local: Vector3<f32>) {
+    let mut camera = Camera::from_view(vp);
+    let altitude = camera.position.norm() - PLANET_RADIUS;
+    let speed = f32::max(CAMERA_FLY_MIN_SPEED, altitude * CAMERA_FLY_SPEED);
+    camera.position += camera.orientation * local * speed;
+    camera.store(vp);
+}
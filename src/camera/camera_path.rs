@@ -0,0 +1,287 @@
+use std::f32::consts::{PI, TAU};
+
+use nalgebra::{Quaternion, UnitQuaternion, Vector3};
+
+use crate::{
+    camera::camera_controller::Camera,
+    collections::{
+        consts::{CAMERA_FREE_FLY, CAMERA_PATH_KEYFRAME_SPACING},
+        structs::{CameraPath, Keyframe, Params},
+    },
+};
+
+fn keyframe_position(k: &Keyframe) -> Vector3<f32> {
+    Vector3::from(k.position)
+}
+
+fn keyframe_rotation(k: &Keyframe) -> UnitQuaternion<f32> {
+    let [x, y, z, w] = k.rotation;
+    UnitQuaternion::from_quaternion(Quaternion::new(w, x, y, z))
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+// Around the short way, so a path crossing +-PI doesn't swing the sun the long way round
+fn lerp_angle(a: f32, b: f32, t: f32) -> f32 {
+    let delta = (b - a + PI).rem_euclid(TAU) - PI;
+    a + delta * t
+}
+
+// Uniform Catmull-Rom through p1 and p2
+fn catmull_rom(
+    p0: &Vector3<f32>,
+    p1: &Vector3<f32>,
+    p2: &Vector3<f32>,
+    p3: &Vector3<f32>,
+    t: f32,
+) -> Vector3<f32> {
+    let t2 = t * t;
+    let t3 = t2 * t;
+
+    (p1 * 2.0
+        + (p2 - p0) * t
+        + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * t2
+        + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * t3)
+        * 0.5
+}
+
+// Shortest arc, quaternions q and -q are the same rotation
+fn slerp(a: &UnitQuaternion<f32>, b: &UnitQuaternion<f32>, t: f32) -> UnitQuaternion<f32> {
+    let b = if a.coords.dot(&b.coords) < 0.0 {
+        UnitQuaternion::new_unchecked(-b.into_inner())
+    } else {
+        *b
+    };
+
+    a.try_slerp(&b, t, f32::EPSILON).unwrap_or(b)
+}
+
+pub(crate) fn path_duration(path: &CameraPath) -> f32 {
+    path.keyframes.last().map_or(0.0, |k| k.path_time)
+}
+
+// Current camera and animated params, placed after the last keyframe
+pub(crate) fn capture_keyframe(path: &CameraPath, params: &Params, scene_time: f32) -> Keyframe {
+    let camera = Camera::from_view(&params.view_params);
+    let q = camera.orientation.quaternion();
+    let path_time = match path.keyframes.last() {
+        Some(last) => last.path_time + CAMERA_PATH_KEYFRAME_SPACING,
+        None => 0.0,
+    };
+
+    Keyframe {
+        path_time,
+        scene_time,
+        position: camera.position.into(),
+        rotation: [q.i, q.j, q.k, q.w],
+        fov_degrees: params.view_params.fov_degrees,
+        exposure: params.post_params.exposure,
+        sun_azimuth: params.light_params.sun_azimuth,
+        sun_elevation: params.light_params.sun_elevation,
+        cloud_coverage: params.cloud_params.coverage,
+    }
+}
+
+// Catmull-Rom for position, slerp for rotation and linear for everything else,
+// the sun azimuth takes the shorter way around
+pub(crate) fn sample_camera_path(path: &CameraPath, time: f32) -> Option<Keyframe> {
+    let keys = &path.keyframes;
+    let first = keys.first()?;
+
+    if keys.len() == 1 || time <= first.path_time {
+        return Some(*first);
+    }
+
+    // Index of the keyframe ending the segment that contains `time`
+    let Some(i) = keys.iter().position(|k| k.path_time > time) else {
+        return keys.last().copied();
+    };
+
+    let k1 = &keys[i - 1];
+    let k2 = &keys[i];
+    // The ends repeat their keyframe in place of the missing neighbour
+    let k0 = &keys[i.saturating_sub(2)];
+    let k3 = &keys[(i + 1).min(keys.len() - 1)];
+
+    let span = (k2.path_time - k1.path_time).max(f32::EPSILON);
+    let t = ((time - k1.path_time) / span).clamp(0.0, 1.0);
+
+    let position = catmull_rom(
+        &keyframe_position(k0),
+        &keyframe_position(k1),
+        &keyframe_position(k2),
+        &keyframe_position(k3),
+        t,
+    );
+    let rotation = slerp(&keyframe_rotation(k1), &keyframe_rotation(k2), t);
+    let q = rotation.quaternion();
+
+    Some(Keyframe {
+        path_time: time,
+        scene_time: lerp(k1.scene_time, k2.scene_time, t),
+        position: position.into(),
+        rotation: [q.i, q.j, q.k, q.w],
+        fov_degrees: lerp(k1.fov_degrees, k2.fov_degrees, t),
+        exposure: lerp(k1.exposure, k2.exposure, t),
+        sun_azimuth: lerp_angle(k1.sun_azimuth, k2.sun_azimuth, t),
+        sun_elevation: lerp(k1.sun_elevation, k2.sun_elevation, t),
+        cloud_coverage: lerp(k1.cloud_coverage, k2.cloud_coverage, t),
+    })
+}
+
+// Playback flies the camera, so it stays where the path ends
+pub(crate) fn apply_keyframe(params: &mut Params, k: &Keyframe) {
+    let vp = &mut params.view_params;
+    vp.controller = CAMERA_FREE_FLY;
+    vp.fov_degrees = k.fov_degrees;
+    Camera {
+        position: keyframe_position(k),
+        orientation: keyframe_rotation(k),
+    }
+    .store(vp);
+
+    params.post_params.exposure = k.exposure;
+    params.light_params.sun_azimuth = k.sun_azimuth;
+    params.light_params.sun_elevation = k.sun_elevation;
+    params.cloud_params.coverage = k.cloud_coverage;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use nalgebra::{UnitQuaternion, Vector3};
+
+    use super::{keyframe_rotation, sample_camera_path, slerp};
+    use crate::collections::structs::{CameraPath, Keyframe};
+
+    fn keyframe(path_time: f32, position: [f32; 3], sun_azimuth: f32) -> Keyframe {
+        Keyframe {
+            path_time,
+            scene_time: path_time,
+            position,
+            rotation: [0.0, 0.0, 0.0, 1.0],
+            fov_degrees: 60.0,
+            exposure: 0.0,
+            sun_azimuth,
+            sun_elevation: 0.1,
+            cloud_coverage: 0.5,
+        }
+    }
+
+    fn path(keyframes: Vec<Keyframe>) -> CameraPath {
+        CameraPath { seed: 0, keyframes }
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-4, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn empty_path_has_no_samples() {
+        assert!(sample_camera_path(&path(Vec::new()), 0.0).is_none());
+    }
+
+    #[test]
+    fn single_keyframe_holds_at_every_time() {
+        let p = path(vec![keyframe(0.0, [1.0, 2.0, 3.0], 0.5)]);
+
+        for time in [-1.0, 0.0, 5.0] {
+            let k = sample_camera_path(&p, time).unwrap();
+            assert_eq!(k.position, [1.0, 2.0, 3.0]);
+            assert_eq!(k.sun_azimuth, 0.5);
+        }
+    }
+
+    #[test]
+    fn curve_passes_through_keyframes() {
+        let keys = vec![
+            keyframe(0.0, [0.0, 0.0, 0.0], 0.0),
+            keyframe(1.0, [4.0, 1.0, 0.0], 0.0),
+            keyframe(3.0, [5.0, -2.0, 3.0], 0.0),
+            keyframe(4.0, [1.0, 0.0, 7.0], 0.0),
+        ];
+        let p = path(keys.clone());
+
+        for k in &keys {
+            let sample = sample_camera_path(&p, k.path_time).unwrap();
+            for axis in 0..3 {
+                assert_close(sample.position[axis], k.position[axis]);
+            }
+        }
+    }
+
+    #[test]
+    fn times_outside_the_path_clamp_to_the_ends() {
+        let p = path(vec![
+            keyframe(1.0, [1.0, 0.0, 0.0], 0.0),
+            keyframe(2.0, [2.0, 0.0, 0.0], 0.0),
+            keyframe(3.0, [3.0, 0.0, 0.0], 0.0),
+        ]);
+
+        assert_eq!(
+            sample_camera_path(&p, 0.0).unwrap().position,
+            [1.0, 0.0, 0.0]
+        );
+        assert_eq!(
+            sample_camera_path(&p, 9.0).unwrap().position,
+            [3.0, 0.0, 0.0]
+        );
+    }
+
+    #[test]
+    fn first_and_last_segments_use_the_end_keyframes_as_neighbours() {
+        // Evenly spaced points on a line stay on it, even where a neighbour is missing
+        let p = path(vec![
+            keyframe(0.0, [0.0, 0.0, 0.0], 0.0),
+            keyframe(1.0, [1.0, 0.0, 0.0], 0.0),
+            keyframe(2.0, [2.0, 0.0, 0.0], 0.0),
+        ]);
+
+        for time in [0.25, 0.5, 1.5, 1.75] {
+            let sample = sample_camera_path(&p, time).unwrap();
+            assert_close(sample.position[1], 0.0);
+            assert!(sample.position[0] > 0.0 && sample.position[0] < 2.0);
+        }
+    }
+
+    #[test]
+    fn slerp_takes_the_shortest_arc_for_negated_quaternions() {
+        let a = UnitQuaternion::from_axis_angle(&Vector3::y_axis(), 0.2);
+        let b = UnitQuaternion::from_axis_angle(&Vector3::y_axis(), 0.6);
+        let minus_b = UnitQuaternion::new_unchecked(-b.into_inner());
+
+        // Same rotation either way, halfway is 0.4 rather than a turn through the far side
+        for end in [b, minus_b] {
+            let mid = slerp(&a, &end, 0.5);
+            assert_close(mid.angle_to(&a), 0.2);
+            assert_close(mid.angle_to(&b), 0.2);
+        }
+    }
+
+    #[test]
+    fn rotation_keyframes_with_opposite_signs_do_not_spin() {
+        let q = UnitQuaternion::from_axis_angle(&Vector3::x_axis(), 0.3);
+        let mut k1 = keyframe(0.0, [0.0; 3], 0.0);
+        let mut k2 = keyframe(1.0, [0.0; 3], 0.0);
+        k1.rotation = [q.i, q.j, q.k, q.w];
+        k2.rotation = [-q.i, -q.j, -q.k, -q.w];
+
+        let sample = sample_camera_path(&path(vec![k1, k2]), 0.5).unwrap();
+        assert_close(keyframe_rotation(&sample).angle_to(&q), 0.0);
+    }
+
+    #[test]
+    fn sun_azimuth_wraps_the_short_way() {
+        let p = path(vec![
+            keyframe(0.0, [0.0; 3], PI - 0.1),
+            keyframe(1.0, [0.0; 3], -PI + 0.1),
+        ]);
+
+        let mid = sample_camera_path(&p, 0.5).unwrap().sun_azimuth;
+        assert_close(mid.sin(), 0.0);
+        assert_close(mid.cos(), -1.0);
+    }
+}
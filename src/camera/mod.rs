@@ -0,0 +1,3 @@
+pub(crate) mod camera_controller;
+pub(crate) mod camera_path;
+pub(crate) mod surface_camera;
@@ -0,0 +1,118 @@
+use nalgebra::{Matrix3, Rotation3, UnitQuaternion, Vector3};
+
+use crate::{
+    analysis::height_field::HeightField,
+    camera::camera_controller::{sync_camera, Camera},
+    collections::{
+        consts::{
+            CAMERA_FLY_MIN_SPEED, CAMERA_FLY_SPEED, CAMERA_MAX_PITCH, CAMERA_SURFACE,
+            CAMERA_SURFACE_MAX_HEIGHT, CAMERA_SURFACE_MAX_LATITUDE, CAMERA_SURFACE_MIN_HEIGHT,
+            PLANET_RADIUS, PLANET_ROTATION,
+        },
+        structs::ViewParams,
+    },
+};
+
+// frag.wgsl samples the terrain at Ry(PLANET_ROTATION * time) * pos,
+// this takes world space into the planet's rotating frame
+fn planet_rotation(time: f32) -> UnitQuaternion<f32> {
+    UnitQuaternion::from_axis_angle(&Vector3::y_axis(), PLANET_ROTATION * time)
+}
+
+fn surface_up(latitude: f32, longitude: f32) -> Vector3<f32> {
+    Vector3::new(
+        latitude.cos() * longitude.sin(),
+        latitude.sin(),
+        latitude.cos() * longitude.cos(),
+    )
+}
+
+// North and east along the ground, latitude is clamped short of the poles
+fn tangent_frame(up: &Vector3<f32>) -> (Vector3<f32>, Vector3<f32>) {
+    let north = (Vector3::y() - up * up.y).normalize();
+    let east = up.cross(&north);
+    (north, east)
+}
+
+fn set_surface_position(vp: &mut ViewParams, up: &Vector3<f32>) {
+    vp.surface_latitude =
+        up.y.asin()
+            .clamp(-CAMERA_SURFACE_MAX_LATITUDE, CAMERA_SURFACE_MAX_LATITUDE);
+    vp.surface_longitude = f32::atan2(up.x, up.z);
+}
+
+// Heading measured from north towards east
+fn heading_of(direction: &Vector3<f32>, up: &Vector3<f32>) -> f32 {
+    let (north, east) = tangent_frame(up);
+    f32::atan2(direction.dot(&east), direction.dot(&north))
+}
+
+// Lands above wherever the camera currently is, facing the same way
+pub(crate) fn enter_surface_mode(vp: &mut ViewParams, field: &HeightField, time: f32) {
+    let camera = sync_camera(vp);
+    let to_planet = planet_rotation(time);
+    let position = to_planet * camera.position;
+
+    set_surface_position(vp, &position.normalize());
+    let up = surface_up(vp.surface_latitude, vp.surface_longitude);
+    vp.surface_heading = heading_of(&(to_planet * (camera.orientation * Vector3::z())), &up);
+    vp.surface_pitch = 0.0;
+    vp.surface_height = (position.norm() - field.ground_radius(&up))
+        .clamp(CAMERA_SURFACE_MIN_HEIGHT, CAMERA_SURFACE_MAX_HEIGHT);
+    vp.controller = CAMERA_SURFACE;
+
+    update_surface_camera(vp, field, time);
+}
+
+// Rebuilt every frame, the ground turns with the planet and the eye stays
+// surface_height above it
+pub(crate) fn update_surface_camera(vp: &mut ViewParams, field: &HeightField, time: f32) {
+    let up = surface_up(vp.surface_latitude, vp.surface_longitude);
+    let (north, east) = tangent_frame(&up);
+    let (sin_heading, cos_heading) = vp.surface_heading.sin_cos();
+    let (sin_pitch, cos_pitch) = vp.surface_pitch.sin_cos();
+
+    let level = north * cos_heading + east * sin_heading;
+    let forward = level * cos_pitch + up * sin_pitch;
+    let camera_up = up * cos_pitch - level * sin_pitch;
+    let right = camera_up.cross(&forward);
+    let orientation = UnitQuaternion::from_rotation_matrix(&Rotation3::from_matrix_unchecked(
+        Matrix3::from_columns(&[right, camera_up, forward]),
+    ));
+
+    let eye = up * (field.ground_radius(&up) + vp.surface_height);
+    let to_world = planet_rotation(time).inverse();
+
+    Camera {
+        position: to_world * eye,
+        orientation: to_world * orientation,
+    }
+    .store(vp);
+}
+
+pub(crate) fn turn_surface_camera(vp: &mut ViewParams, heading: f32, pitch: f32) {
+    vp.surface_heading += heading;
+    vp.surface_pitch = (vp.surface_pitch + pitch).clamp(-CAMERA_MAX_PITCH, CAMERA_MAX_PITCH);
+}
+
+// Moves along the ground, faster the higher the eye is
+pub(crate) fn walk_surface_camera(vp: &mut ViewParams, right: f32, forward: f32) {
+    let up = surface_up(vp.surface_latitude, vp.surface_longitude);
+    let (north, east) = tangent_frame(&up);
+    let (sin_heading, cos_heading) = vp.surface_heading.sin_cos();
+    let level = north * cos_heading + east * sin_heading;
+    let side = up.cross(&level);
+
+    let speed = f32::max(CAMERA_FLY_MIN_SPEED, vp.surface_height * CAMERA_FLY_SPEED);
+    let step = (level * forward + side * right) * (speed / PLANET_RADIUS);
+    let new_up = (up + step).normalize();
+
+    // Carry the heading over so walking doesn't curve near the poles
+    vp.surface_heading = heading_of(&level, &new_up);
+    set_surface_position(vp, &new_up);
+}
+
+pub(crate) fn climb_surface_camera(vp: &mut ViewParams, amount: f32) {
+    vp.surface_height = (vp.surface_height * (1.0 + amount))
+        .clamp(CAMERA_SURFACE_MIN_HEIGHT, CAMERA_SURFACE_MAX_HEIGHT);
+}
@@ -1,6 +1,37 @@
+pub(crate) const WINDOW_TITLE: &str = "planets_wgpu";
+// Path to an equirectangular .hdr background, e.g. PLANETS_ENV_MAP=sky.hdr
+pub(crate) const ENV_MAP_VAR: &str = "PLANETS_ENV_MAP";
+// Camera path saved from PATH mode, played back from the first frame
+pub(crate) const CAMERA_PATH_VAR: &str = "PLANETS_CAMERA_PATH";
+// Watched for edits while running, shaders are rebuilt without a restart
+pub(crate) const SHADER_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/shaders");
+// Seconds between checks of the shader files' modification times
+pub(crate) const SHADER_POLL_INTERVAL: f32 = 0.5;
+
 pub(crate) const SCREEN_WIDTH: u32 = 1376;
 pub(crate) const SCREEN_HEIGHT: u32 = 768;
 pub(crate) const ASPECT: f32 = SCREEN_WIDTH as f32 / SCREEN_HEIGHT as f32;
+// The scene is rendered in linear HDR and tonemapped by the post process
+pub(crate) const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+// Screen space motion written alongside the HDR colour for TAA
+pub(crate) const MOTION_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rg16Float;
+
+// Anti-aliasing modes, injected into the shaders
+pub(crate) const AA_OFF: f32 = 0.0;
+pub(crate) const AA_SSAA: f32 = 1.0;
+pub(crate) const AA_TAA: f32 = 2.0;
+
+// Debug views, injected into the shaders
+pub(crate) const DEBUG_VIEW_OFF: f32 = 0.0;
+pub(crate) const DEBUG_VIEW_NORMALS: f32 = 1.0;
+pub(crate) const DEBUG_VIEW_SLOPE: f32 = 2.0;
+pub(crate) const DEBUG_VIEW_STEPS: f32 = 3.0;
+pub(crate) const DEBUG_VIEW_MATERIAL: f32 = 4.0;
+pub(crate) const DEBUG_VIEW_WATER_DEPTH: f32 = 5.0;
+pub(crate) const DEBUG_VIEW_HEIGHTMAP: f32 = 6.0;
+pub(crate) const DEBUG_VIEW_WAVE_DIRECTION: f32 = 7.0;
+pub(crate) const DEBUG_VIEW_TRIPLANAR: f32 = 8.0;
+pub(crate) const DEBUG_VIEW_OVERSHOOT: f32 = 9.0;
 
 pub(crate) const PLANET_TEXTURE_WIDTH: u32 = 2048;
 pub(crate) const PLANET_TEXTURE_HEIGHT: u32 = 2048;
@@ -20,3 +51,60 @@ pub(crate) const PLANET_TEX_BUF_SIZE: usize = PLANET_TEXTURE_WIDTH as usize
 
 pub(crate) const MOON_TEX_BUF_SIZE: usize =
     MOON_TEXTURE_WIDTH as usize * MOON_TEXTURE_HEIGHT as usize * 4 * (std::mem::size_of::<f32>());
+
+// Injected into the shaders, see shader_compose.rs
+pub(crate) const PLANET_RADIUS: f32 = 50.0;
+pub(crate) const PLANET_ROTATION: f32 = 0.1;
+pub(crate) const WATER_LEVEL: f32 = 50.3;
+pub(crate) const ICE_LEVEL: f32 = WATER_LEVEL + 3.3;
+// Planet surface radius is PLANET_RADIUS - tx.x * PLANET_TERRAIN_AMP
+pub(crate) const PLANET_TERRAIN_AMP: f32 = 10.0;
+
+// Injected into the shaders
+pub(crate) const MOON_RADIUS: f32 = 8.0;
+pub(crate) const MOON_ORBIT_SPEED: f32 = 0.2;
+pub(crate) const MOON_ORBIT_RADIUS: f32 = 75.0;
+pub(crate) const MOON_ORBIT_INCLINATION: f32 = 5.0;
+
+pub(crate) const WAVE_WORKGROUP_SIZE: u32 = 16;
+pub(crate) const WAVE_DISPATCH_SIZE_X: u32 = PLANET_TEXTURE_WIDTH / WAVE_WORKGROUP_SIZE;
+pub(crate) const WAVE_DISPATCH_SIZE_Y: u32 = PLANET_TEXTURE_HEIGHT / WAVE_WORKGROUP_SIZE;
+// Jump flood steps are read through a dynamic uniform offset,
+// 256 satisfies every adapter's offset alignment
+pub(crate) const JUMP_FLOOD_STEP_STRIDE: u64 = 256;
+
+// Camera controllers
+pub(crate) const CAMERA_ORBIT: f32 = 0.0;
+pub(crate) const CAMERA_FREE_FLY: f32 = 1.0;
+pub(crate) const CAMERA_SURFACE: f32 = 2.0;
+// Keeps the orbit outside the tallest mountains
+pub(crate) const CAMERA_MIN_ORBIT_DISTANCE: f32 = PLANET_RADIUS + PLANET_TERRAIN_AMP + 2.0;
+pub(crate) const CAMERA_MAX_ORBIT_DISTANCE: f32 = 400.0;
+pub(crate) const CAMERA_MAX_PITCH: f32 = 1.55;
+// Free-fly speed per key press, as a fraction of the altitude
+pub(crate) const CAMERA_FLY_SPEED: f32 = 0.02;
+pub(crate) const CAMERA_FLY_MIN_SPEED: f32 = 0.01;
+// Eye height above the ground or sea for the surface camera
+pub(crate) const CAMERA_SURFACE_MIN_HEIGHT: f32 = 0.2;
+pub(crate) const CAMERA_SURFACE_MAX_HEIGHT: f32 = CAMERA_MAX_ORBIT_DISTANCE - PLANET_RADIUS;
+// North is undefined at the poles
+pub(crate) const CAMERA_SURFACE_MAX_LATITUDE: f32 = 1.55;
+
+// Seconds between keyframes added from PATH mode
+pub(crate) const CAMERA_PATH_KEYFRAME_SPACING: f32 = 3.0;
+// Playback advances a fixed step per frame so every run renders the same frames
+pub(crate) const CAMERA_PATH_FRAME_TIME: f32 = 1.0 / 60.0;
+
+// Fixed orbit views for the ray march benchmark, (name, yaw, pitch, distance)
+pub(crate) const RAY_MARCH_BENCHMARK_VIEWS: [(&str, f32, f32, f32); 4] = [
+    ("full disc", 0.0, 0.0, 300.0),
+    ("zoomed", 0.0, 0.0, 120.0),
+    ("close up", 1.2, 0.4, 66.0),
+    ("pole", 0.0, 1.4, 160.0),
+];
+pub(crate) const RAY_MARCH_BENCHMARK_FRAMES: u32 = 10;
+// Time uniform during the benchmark, so every run sees the moon in the same place
+pub(crate) const RAY_MARCH_BENCHMARK_TIME: f32 = 10.0;
+
+// Screenshots and exported reports are written here
+pub(crate) const SCREENSHOT_DIR: &str = "screenshots";
@@ -0,0 +1,45 @@
+use serde::{Deserialize, Serialize};
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable, Serialize, Deserialize)]
+pub(crate) struct Material {
+    // Multiplies the procedural biome colour from frag.wgsl
+    pub(crate) albedo: [f32; 3],
+    // Perceptual roughness, GGX alpha is roughness^2
+    pub(crate) roughness: f32,
+    pub(crate) metallic: f32,
+    // Dielectric reflectance at normal incidence (F0)
+    pub(crate) reflectance: f32,
+    #[serde(skip)]
+    pub(crate) _padding: [f32; 2],
+}
+
+impl Material {
+    const fn new(albedo: f32, roughness: f32, metallic: f32, reflectance: f32) -> Self {
+        Self {
+            albedo: [albedo; 3],
+            roughness,
+            metallic,
+            reflectance,
+            _padding: [0.0; 2],
+        }
+    }
+}
+
+pub(crate) const MATERIAL_COUNT: usize = 6;
+
+// Order must match the fields of MaterialEnum in frag.wgsl
+pub(crate) const MATERIALS: [Material; MATERIAL_COUNT] = [
+    // Ice
+    Material::new(0.7, 0.3, 0.0, 0.018),
+    // Water
+    Material::new(0.63, 0.08, 0.0, 0.02),
+    // Rock
+    Material::new(0.25, 0.85, 0.0, 0.04),
+    // Plant
+    Material::new(0.18, 0.7, 0.0, 0.04),
+    // Sand
+    Material::new(0.35, 0.9, 0.0, 0.04),
+    // Moon
+    Material::new(0.42, 0.95, 0.0, 0.04),
+];
@@ -1,3 +1,4 @@
 pub(crate) mod consts;
+pub(crate) mod materials;
 pub(crate) mod structs;
 pub(crate) mod vertices;
@@ -1,3 +1,7 @@
+use serde::{Deserialize, Serialize};
+
+use super::materials::{Material, MATERIAL_COUNT};
+
 #[repr(C)]
 #[derive(Copy, Clone)]
 pub(crate) struct TimeUniform {
@@ -10,15 +14,23 @@ pub(crate) struct Buffers {
     pub(crate) time_uniform: wgpu::Buffer,
     pub(crate) terrain_params: wgpu::Buffer,
     pub(crate) ray_params: wgpu::Buffer,
-    pub(crate) view_params: wgpu::Buffer,
-    pub(crate) planet_tex_buffer: wgpu::Buffer,
+    pub(crate) camera_params: wgpu::Buffer,
+    pub(crate) water_params: wgpu::Buffer,
+    pub(crate) atmosphere_params: wgpu::Buffer,
+    pub(crate) cloud_params: wgpu::Buffer,
+    pub(crate) light_params: wgpu::Buffer,
+    pub(crate) background_params: wgpu::Buffer,
+    pub(crate) ring_params: wgpu::Buffer,
+    pub(crate) post_params: wgpu::Buffer,
+    pub(crate) aa_params: wgpu::Buffer,
+    pub(crate) materials: wgpu::Buffer,
     pub(crate) debug_params: wgpu::Buffer,
     pub(crate) generic_debug: wgpu::Buffer,
-    pub(crate) cpu_read_generic_debug: wgpu::Buffer,
     pub(crate) debug_array1: wgpu::Buffer,
-    pub(crate) cpu_read_debug_array1: wgpu::Buffer,
     pub(crate) debug_array2: wgpu::Buffer,
-    pub(crate) cpu_read_debug_array2: wgpu::Buffer,
+    pub(crate) coast_seeds_a: wgpu::Buffer,
+    pub(crate) coast_seeds_b: wgpu::Buffer,
+    pub(crate) jump_flood_steps: wgpu::Buffer,
 }
 
 #[derive(Debug)]
@@ -33,6 +45,10 @@ pub(crate) struct BindGroups {
     pub(crate) texture_bgl: wgpu::BindGroupLayout,
     pub(crate) sampled_texture_bg: wgpu::BindGroup,
     pub(crate) sampled_texture_bgl: wgpu::BindGroupLayout,
+    // Jump flood ping-pong, a -> b and b -> a
+    pub(crate) wave_bg_ab: wgpu::BindGroup,
+    pub(crate) wave_bg_ba: wgpu::BindGroup,
+    pub(crate) wave_bgl: wgpu::BindGroupLayout,
 }
 
 #[derive(Debug)]
@@ -40,6 +56,8 @@ pub(crate) struct ShaderModules {
     pub(crate) v_shader: wgpu::ShaderModule,
     pub(crate) f_shader: wgpu::ShaderModule,
     pub(crate) generate_terrain: wgpu::ShaderModule,
+    pub(crate) wave_directions: wgpu::ShaderModule,
+    pub(crate) post: wgpu::ShaderModule,
 }
 
 #[derive(Debug)]
@@ -47,83 +65,313 @@ pub(crate) struct Pipelines {
     pub(crate) render: wgpu::RenderPipeline,
     pub(crate) generate_planet_terrain: wgpu::ComputePipeline,
     pub(crate) generate_moon_terrain: wgpu::ComputePipeline,
+    pub(crate) init_coast_seeds: wgpu::ComputePipeline,
+    pub(crate) jump_flood: wgpu::ComputePipeline,
+    pub(crate) write_wave_directions: wgpu::ComputePipeline,
 }
 
 #[derive(Debug)]
 pub(crate) struct Textures {
     pub(crate) planet_tex: wgpu::Texture,
     pub(crate) planet_tex_extent: wgpu::Extent3d,
+    pub(crate) moon_tex: wgpu::Texture,
     pub(crate) planet_sampler: wgpu::Sampler,
     pub(crate) planet_view: wgpu::TextureView,
     pub(crate) moon_sampler: wgpu::Sampler,
     pub(crate) moon_view: wgpu::TextureView,
+    pub(crate) env_sampler: wgpu::Sampler,
+    pub(crate) env_view: wgpu::TextureView,
 }
 
 #[derive(Debug)]
 pub(crate) struct PlanetTexture {
     pub(crate) planet_tex: wgpu::Texture,
     pub(crate) planet_tex_extent: wgpu::Extent3d,
-}
-
-#[derive(Debug, Copy, Clone, PartialEq)]
-pub(crate) struct Point {
-    pub(crate) elevation: f32,
-    pub(crate) x: u32,
-    pub(crate) y: u32,
-}
-
-impl Point {
-    pub(crate) fn manhattan_distance(&self, other: &Point) -> u32 {
-        let dx = (self.x as i32 - other.x as i32).abs() as u32;
-        let dy = (self.y as i32 - other.y as i32).abs() as u32;
-        dx + dy
-    }
-}
-
-impl PartialOrd for Point {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        return self.elevation.partial_cmp(&other.elevation);
-    }
+    // Cleared along with the planet texture when the terrain is regenerated
+    pub(crate) moon_tex: wgpu::Texture,
 }
 
 // PARAMETERS
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub(crate) struct Params {
     pub(crate) terrain_params: TerrainParams,
     pub(crate) ray_params: RayParams,
     pub(crate) view_params: ViewParams,
+    pub(crate) water_params: WaterParams,
+    pub(crate) atmosphere_params: AtmosphereParams,
+    pub(crate) cloud_params: CloudParams,
+    pub(crate) light_params: LightParams,
+    pub(crate) background_params: BackgroundParams,
+    pub(crate) ring_params: RingParams,
+    pub(crate) post_params: PostParams,
+    pub(crate) aa_params: AntiAliasParams,
+    pub(crate) materials: [Material; MATERIAL_COUNT],
     pub(crate) debug_params: DebugParams,
 }
 
 #[repr(C)]
-#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable, Serialize, Deserialize)]
 pub(crate) struct RayParams {
     pub(crate) epsilon: f32,
-    pub(crate) max_steps: f32,
     pub(crate) max_dist: f32,
+    pub(crate) max_steps: f32,
+    // Bounding spheres and distance-scaled epsilon, 1.0 or 0.0
+    pub(crate) accelerate: f32,
+    // Step multiplier, over-relaxed steps above 1.0 are undone when they skip geometry
+    pub(crate) relaxation: f32,
+    // Lower bound on the terrain SDF's gradient, steps are divided by it
+    pub(crate) gradient_bound: f32,
 }
 
 #[repr(C)]
-#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable, Serialize, Deserialize)]
 pub(crate) struct TerrainParams {
     pub(crate) octaves: i32,
+    // Offsets the terrain noise and crater placement, 0 is the original planet
+    pub(crate) seed: u32,
 }
 
 #[repr(C)]
-#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable, Serialize, Deserialize)]
 pub(crate) struct DebugParams {
-    pub(crate) pole_start: f32,
-    pub(crate) pole_scale: f32,
+    // One of the DEBUG_VIEW consts
+    pub(crate) view: f32,
+    // Heightmap channel shown by DEBUG_VIEW_HEIGHTMAP, 0 -> 3
+    pub(crate) heightmap_channel: f32,
+    // Step count at the top of the DEBUG_VIEW_STEPS heatmap
+    pub(crate) step_range: f32,
 }
 
-#[repr(C)]
-#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+// Host side only, the GPU sees the camera through CameraParams
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub(crate) struct ViewParams {
-    pub(crate) x_shift: f32,
-    pub(crate) y_shift: f32,
-    pub(crate) zoom: f32,
-    pub(crate) x_rot: f32,
-    pub(crate) y_rot: f32,
+    // CAMERA_ORBIT, CAMERA_FREE_FLY or CAMERA_SURFACE
+    pub(crate) controller: f32,
+    // Orbit around the planet centre, angles in radians
+    pub(crate) orbit_yaw: f32,
+    pub(crate) orbit_pitch: f32,
+    pub(crate) orbit_distance: f32,
+    // Surface camera, in the planet's rotating frame. Heading is from north
+    // towards east, height is the eye's height above the ground
+    pub(crate) surface_latitude: f32,
+    pub(crate) surface_longitude: f32,
+    pub(crate) surface_heading: f32,
+    pub(crate) surface_pitch: f32,
+    pub(crate) surface_height: f32,
+    // Camera pose, driven by the orbit or surface camera while they're active
+    pub(crate) position_x: f32,
+    pub(crate) position_y: f32,
+    pub(crate) position_z: f32,
+    pub(crate) rotation_x: f32,
+    pub(crate) rotation_y: f32,
+    pub(crate) rotation_z: f32,
+    pub(crate) rotation_w: f32,
+    // Vertical field of view
+    pub(crate) fov_degrees: f32,
+    pub(crate) near: f32,
+    pub(crate) far: f32,
     pub(crate) time_modifier: f32,
+}
+
+// Written to the GPU from ViewParams every frame, checked against frag.wgsl by layout_check.rs
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct CameraParams {
+    pub(crate) position_x: f32,
+    pub(crate) position_y: f32,
+    pub(crate) position_z: f32,
+    pub(crate) fov_y: f32,
+    pub(crate) rotation_x: f32,
+    pub(crate) rotation_y: f32,
+    pub(crate) rotation_z: f32,
+    pub(crate) rotation_w: f32,
+    pub(crate) near: f32,
+    pub(crate) far: f32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable, Serialize, Deserialize)]
+pub(crate) struct WaterParams {
+    pub(crate) wave_amplitude: f32,
+    pub(crate) wave_speed: f32,
+    // Waves per planet texture width
+    pub(crate) wave_frequency: f32,
+    // Extra amplitude gained in shallow water
+    pub(crate) shoaling: f32,
+    // Water depth over which shoreline foam fades out
+    pub(crate) foam_width: f32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable, Serialize, Deserialize)]
+pub(crate) struct AtmosphereParams {
+    // Top of the atmosphere, measured from the planet center
+    pub(crate) outer_radius: f32,
+    // Scales both scattering coefficients, 0.0 turns the atmosphere off
+    pub(crate) density: f32,
+    pub(crate) rayleigh_scale_height: f32,
+    pub(crate) mie_scale_height: f32,
+    // Rayleigh scattering per unit length at PLANET_RADIUS, falls off with wavelength^4
+    pub(crate) rayleigh_r: f32,
+    pub(crate) rayleigh_g: f32,
+    pub(crate) rayleigh_b: f32,
+    pub(crate) mie_coefficient: f32,
+    // Mie phase anisotropy, haze scatters forwards as it approaches 1.0
+    pub(crate) mie_g: f32,
+    pub(crate) sun_intensity: f32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable, Serialize, Deserialize)]
+pub(crate) struct CloudParams {
+    // 1.0 or 0.0, the cloud march is skipped entirely when off
+    pub(crate) enabled: f32,
+    // Fraction of the sky covered, 0.0 -> 1.0
+    pub(crate) coverage: f32,
+    // Cloud base above PLANET_RADIUS
+    pub(crate) altitude: f32,
+    pub(crate) thickness: f32,
+    // Extra angular speed on top of the planet rotation
+    pub(crate) wind_speed: f32,
+    // Extinction per unit length inside a fully covered cloud
+    pub(crate) density: f32,
+    pub(crate) noise_scale: f32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable, Serialize, Deserialize)]
+pub(crate) struct LightParams {
+    // Sun direction from the planet center at time 0, in radians
+    pub(crate) sun_azimuth: f32,
+    pub(crate) sun_elevation: f32,
+    pub(crate) sun_distance: f32,
+    // Radians per second around the planet's axis, 0.0 stops the day/night cycle
+    pub(crate) orbit_speed: f32,
+    pub(crate) sun_r: f32,
+    pub(crate) sun_g: f32,
+    pub(crate) sun_b: f32,
+    pub(crate) sun_intensity: f32,
+    // Apparent radius of the sun disc, in radians
+    pub(crate) angular_radius: f32,
+    // Directional fill light without shadows, 1.0 or 0.0
+    pub(crate) fill_enabled: f32,
+    pub(crate) fill_azimuth: f32,
+    pub(crate) fill_elevation: f32,
+    pub(crate) fill_r: f32,
+    pub(crate) fill_g: f32,
+    pub(crate) fill_b: f32,
+    pub(crate) fill_intensity: f32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable, Serialize, Deserialize)]
+pub(crate) struct BackgroundParams {
+    // Star cells across the sky, higher packs in more and smaller stars
+    pub(crate) star_scale: f32,
+    // Fraction of cells holding a star, 0.0 -> 1.0
+    pub(crate) star_density: f32,
+    pub(crate) star_brightness: f32,
+    // Milky Way band, 1.0 or 0.0
+    pub(crate) nebula_enabled: f32,
+    pub(crate) nebula_intensity: f32,
+    // Brightness of the sun disc relative to the sun colour
+    pub(crate) sun_disc_intensity: f32,
+    // Set at startup from whether an HDR image was loaded, replaces stars and nebula
+    pub(crate) env_map_enabled: f32,
+    pub(crate) env_intensity: f32,
+    // Rotation of the HDR image around the planet's axis, in radians
+    pub(crate) env_rotation: f32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable, Serialize, Deserialize)]
+pub(crate) struct RingParams {
+    // 1.0 or 0.0, nothing is traced against the ring plane when off
+    pub(crate) enabled: f32,
+    // Distances from the planet center within the ring plane
+    pub(crate) inner_radius: f32,
+    pub(crate) outer_radius: f32,
+    // Angle of the ring plane from the equator, in radians around the x axis
+    pub(crate) tilt: f32,
+    // Opacity of the densest bands, 0.0 -> 1.0
+    pub(crate) opacity: f32,
+    // Density bands across the width of the rings
+    pub(crate) band_frequency: f32,
+    // Mie phase anisotropy, the rings light up when backlit as it approaches 1.0
+    pub(crate) scatter_g: f32,
+    // Colour at the inner edge, blended into the outer colour across the rings
+    pub(crate) inner_r: f32,
+    pub(crate) inner_g: f32,
+    pub(crate) inner_b: f32,
+    pub(crate) outer_r: f32,
+    pub(crate) outer_g: f32,
+    pub(crate) outer_b: f32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable, Serialize, Deserialize)]
+pub(crate) struct PostParams {
+    // Every stage is 1.0 or 0.0
+    pub(crate) exposure_enabled: f32,
+    // In stops, 1.0 doubles the brightness
+    pub(crate) exposure: f32,
+    // 0.0 none (clip), 1.0 ACES, 2.0 Reinhard, 3.0 AgX
+    pub(crate) tonemapper: f32,
+    pub(crate) bloom_enabled: f32,
+    // Luminance above which pixels start to bloom
+    pub(crate) bloom_threshold: f32,
+    pub(crate) bloom_intensity: f32,
+    pub(crate) srgb_enabled: f32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable, Serialize, Deserialize)]
+pub(crate) struct AntiAliasParams {
+    // 0.0 off, 1.0 NxN supersampling, 2.0 temporal
+    pub(crate) mode: f32,
+    // N, the number of rays per pixel is N * N
+    pub(crate) ssaa_samples: f32,
+    // Weight of the history in the TAA blend
+    pub(crate) taa_blend: f32,
+    // Everything below is written every frame
+    pub(crate) history_valid: f32,
+    // Sub-pixel ray offset, in pixels
+    pub(crate) jitter_x: f32,
+    pub(crate) jitter_y: f32,
+    pub(crate) frame_index: f32,
+    // Time and camera of the previous frame, for reprojecting the history
+    pub(crate) prev_time: f32,
+    pub(crate) prev_position_x: f32,
+    pub(crate) prev_position_y: f32,
+    pub(crate) prev_position_z: f32,
+    pub(crate) prev_fov_y: f32,
+    pub(crate) prev_rotation_x: f32,
+    pub(crate) prev_rotation_y: f32,
+    pub(crate) prev_rotation_z: f32,
+    pub(crate) prev_rotation_w: f32,
+}
+
+// CAMERA PATHS
+// Pose and the handful of params a shot animates, everything else stays as set
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub(crate) struct Keyframe {
+    // Seconds from the start of the path
+    pub(crate) path_time: f32,
+    // Time uniform at this keyframe, places the planet, moon, sun and clouds
+    pub(crate) scene_time: f32,
+    pub(crate) position: [f32; 3],
+    // x, y, z, w
+    pub(crate) rotation: [f32; 4],
     pub(crate) fov_degrees: f32,
+    pub(crate) exposure: f32,
+    pub(crate) sun_azimuth: f32,
+    pub(crate) sun_elevation: f32,
+    pub(crate) cloud_coverage: f32,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub(crate) struct CameraPath {
+    // Terrain seed the path was recorded on
+    pub(crate) seed: u32,
+    pub(crate) keyframes: Vec<Keyframe>,
 }
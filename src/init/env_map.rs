@@ -0,0 +1,34 @@
+use std::{fs::File, io::BufReader, path::Path};
+
+use image::codecs::hdr::HdrDecoder;
+
+// Equirectangular HDR image, longitude along x and latitude along y
+#[derive(Debug)]
+pub(crate) struct EnvMap {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) texels: Vec<[f32; 4]>,
+}
+
+pub(crate) fn load_env_map(path: &Path, max_dimension: u32) -> image::ImageResult<EnvMap> {
+    let decoder = HdrDecoder::new(BufReader::new(File::open(path)?))?;
+    let meta = decoder.metadata();
+
+    if meta.width > max_dimension || meta.height > max_dimension {
+        return Err(image::ImageError::Limits(
+            image::error::LimitError::from_kind(image::error::LimitErrorKind::DimensionError),
+        ));
+    }
+
+    let texels = decoder
+        .read_image_hdr()?
+        .into_iter()
+        .map(|p| [p.0[0], p.0[1], p.0[2], 1.0])
+        .collect();
+
+    Ok(EnvMap {
+        width: meta.width,
+        height: meta.height,
+        texels,
+    })
+}
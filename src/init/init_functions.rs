@@ -1,74 +1,235 @@
 use wgpu::util::DeviceExt;
 
+use crate::analysis::coast_field::jump_flood_steps;
+use crate::camera::camera_controller::{sync_camera, Camera};
 use crate::collections::{
     consts::{
-        MOON_TEXTURE_HEIGHT, MOON_TEXTURE_WIDTH, MOON_TEX_BUF_SIZE, PLANET_TEXTURE_HEIGHT,
+        CAMERA_ORBIT, DEBUG_VIEW_OFF, HDR_FORMAT, JUMP_FLOOD_STEP_STRIDE, MOON_TEXTURE_HEIGHT,
+        MOON_TEXTURE_WIDTH, MOON_TEX_BUF_SIZE, MOTION_FORMAT, PLANET_RADIUS, PLANET_TEXTURE_HEIGHT,
         PLANET_TEXTURE_WIDTH, PLANET_TEX_BUF_SIZE,
     },
+    materials::MATERIALS,
     structs::{
-        BindGroups, Buffers, DebugParams, Params, Pipelines, RayParams, ShaderModules,
-        TerrainParams, Textures, TimeUniform, ViewParams,
+        AntiAliasParams, AtmosphereParams, BackgroundParams, BindGroups, Buffers, CloudParams,
+        DebugParams, LightParams, Params, Pipelines, PostParams, RayParams, RingParams,
+        ShaderModules, TerrainParams, Textures, TimeUniform, ViewParams, WaterParams,
     },
     vertices::{vertices_as_bytes, VERTICES},
 };
+use crate::init::env_map::EnvMap;
+use crate::init::layout_check::check_all_layouts;
+use crate::init::shader_compose::{compose_shader, embedded_source, ShaderFile};
 
 pub(crate) fn init_shader_modules(device: &wgpu::Device) -> ShaderModules {
-    let vdesc = wgpu::ShaderModuleDescriptor {
-        label: Some("Vertex Shader"),
-        source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/v2.wgsl").into()),
-    };
-    let v_shader = device.create_shader_module(vdesc);
-
-    let fdesc = wgpu::ShaderModuleDescriptor {
-        label: Some("Fragment Shader"),
-        source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/frag.wgsl").into()),
-    };
-    let f_shader = device.create_shader_module(fdesc);
+    let composed = ShaderFile::ALL.map(|file| {
+        compose_shader(file, &embedded_source)
+            .unwrap_or_else(|e| panic!("{} should compose: {}", file.name(), e))
+    });
 
-    let generate_terrain_desc = wgpu::ShaderModuleDescriptor {
-        label: Some("Generate Terrain Shader"),
-        source: wgpu::ShaderSource::Wgsl(
-            include_str!("../shaders/compute/generate_terrain.wgsl").into(),
-        ),
-    };
+    // A Rust struct that drifts from its WGSL twin silently scrambles the parameters
+    let parsed: Vec<_> = ShaderFile::ALL
+        .iter()
+        .zip(&composed)
+        .map(|(file, shader)| {
+            let module = naga::front::wgsl::parse_str(&shader.source).unwrap_or_else(|e| {
+                panic!(
+                    "{}",
+                    e.emit_to_string_with_path(&shader.source, file.name())
+                )
+            });
+            (file.name(), module)
+        })
+        .collect();
+    if let Err(errors) = check_all_layouts(&parsed) {
+        panic!("Rust and WGSL struct layouts differ:\n{}", errors);
+    }
 
-    let generate_terrain = device.create_shader_module(generate_terrain_desc);
+    // ShaderFile::ALL lists the shaders in this order
+    let [v_shader, f_shader, generate_terrain, wave_directions, post] = std::array::from_fn(|i| {
+        device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(ShaderFile::ALL[i].label()),
+            source: wgpu::ShaderSource::Wgsl(composed[i].source.as_str().into()),
+        })
+    });
 
     ShaderModules {
         v_shader,
         f_shader,
         generate_terrain,
+        wave_directions,
+        post,
     }
 }
 
 pub(crate) fn init_params() -> Params {
-    let terrain_params = TerrainParams { octaves: 19 };
+    let terrain_params = TerrainParams {
+        octaves: 19,
+        seed: 0,
+    };
 
     let ray_params = RayParams {
         epsilon: 0.02,
-        max_steps: 2500.0,
         max_dist: 500.0,
+        max_steps: 2500.0,
+        accelerate: 1.0,
+        relaxation: 1.2,
+        gradient_bound: 1.5,
     };
 
     let debug_params = DebugParams {
-        pole_start: 0.01,
-        pole_scale: 1.0,
+        view: DEBUG_VIEW_OFF,
+        heightmap_channel: 0.0,
+        step_range: 200.0,
     };
 
-    let view_params = ViewParams {
-        x_shift: 0.0,
-        y_shift: 0.0,
-        zoom: 1.0,
-        x_rot: 0.0,
-        y_rot: 0.0,
-        time_modifier: 1.0,
+    let mut view_params = ViewParams {
+        controller: CAMERA_ORBIT,
+        orbit_yaw: 0.0,
+        orbit_pitch: 0.0,
+        orbit_distance: 300.0,
+        surface_latitude: 0.0,
+        surface_longitude: 0.0,
+        surface_heading: 0.0,
+        surface_pitch: 0.0,
+        surface_height: 1.0,
+        position_x: 0.0,
+        position_y: 0.0,
+        position_z: 0.0,
+        rotation_x: 0.0,
+        rotation_y: 0.0,
+        rotation_z: 0.0,
+        rotation_w: 1.0,
         fov_degrees: 20.0,
+        near: 0.1,
+        far: 600.0,
+        time_modifier: 1.0,
+    };
+    let camera = sync_camera(&mut view_params).params(&view_params);
+
+    let water_params = WaterParams {
+        wave_amplitude: 0.04,
+        wave_speed: 0.02,
+        wave_frequency: 120.0,
+        shoaling: 1.5,
+        foam_width: 0.15,
+    };
+
+    // Earth-like optical depths, with the scale heights stretched
+    // so the atmosphere is visible at planet scale
+    let atmosphere_params = AtmosphereParams {
+        outer_radius: PLANET_RADIUS + 6.0,
+        density: 1.0,
+        rayleigh_scale_height: 1.2,
+        mie_scale_height: 0.4,
+        rayleigh_r: 0.058,
+        rayleigh_g: 0.135,
+        rayleigh_b: 0.331,
+        mie_coefficient: 0.06,
+        mie_g: 0.76,
+        sun_intensity: 20.0,
+    };
+
+    let cloud_params = CloudParams {
+        enabled: 1.0,
+        coverage: 0.45,
+        altitude: 2.5,
+        thickness: 1.2,
+        wind_speed: 0.02,
+        density: 4.0,
+        noise_scale: 0.15,
+    };
+
+    // Matches the old fixed light at (40.0, 50.0, -500.0)
+    let light_params = LightParams {
+        sun_azimuth: 0.08,
+        sun_elevation: 0.1,
+        sun_distance: 504.0,
+        orbit_speed: 0.0,
+        sun_r: 1.0,
+        sun_g: 1.0,
+        sun_b: 1.0,
+        sun_intensity: 1.0,
+        angular_radius: 0.03,
+        fill_enabled: 0.0,
+        fill_azimuth: 2.5,
+        fill_elevation: 0.3,
+        fill_r: 0.4,
+        fill_g: 0.5,
+        fill_b: 0.7,
+        fill_intensity: 0.15,
+    };
+
+    let background_params = BackgroundParams {
+        star_scale: 400.0,
+        star_density: 0.08,
+        star_brightness: 1.0,
+        nebula_enabled: 1.0,
+        nebula_intensity: 0.04,
+        sun_disc_intensity: 40.0,
+        env_map_enabled: 0.0,
+        env_intensity: 1.0,
+        env_rotation: 0.0,
+    };
+
+    // Disabled by default, sized to sit between the atmosphere and the moon's orbit
+    let ring_params = RingParams {
+        enabled: 0.0,
+        inner_radius: PLANET_RADIUS + 10.0,
+        outer_radius: PLANET_RADIUS + 16.0,
+        tilt: 0.2,
+        opacity: 0.8,
+        band_frequency: 12.0,
+        scatter_g: 0.7,
+        inner_r: 0.55,
+        inner_g: 0.48,
+        inner_b: 0.4,
+        outer_r: 0.85,
+        outer_g: 0.78,
+        outer_b: 0.66,
+    };
+
+    let post_params = PostParams {
+        exposure_enabled: 1.0,
+        exposure: 0.0,
+        tonemapper: 1.0,
+        bloom_enabled: 1.0,
+        bloom_threshold: 1.0,
+        bloom_intensity: 0.3,
+        srgb_enabled: 1.0,
+    };
+
+    let aa_params = AntiAliasParams {
+        mode: 0.0,
+        ssaa_samples: 2.0,
+        taa_blend: 0.9,
+        history_valid: 0.0,
+        jitter_x: 0.0,
+        jitter_y: 0.0,
+        frame_index: 0.0,
+        prev_time: 0.0,
+        prev_position_x: camera.position_x,
+        prev_position_y: camera.position_y,
+        prev_position_z: camera.position_z,
+        prev_fov_y: camera.fov_y,
+        prev_rotation_x: camera.rotation_x,
+        prev_rotation_y: camera.rotation_y,
+        prev_rotation_z: camera.rotation_z,
+        prev_rotation_w: camera.rotation_w,
     };
 
     Params {
         terrain_params,
         ray_params,
         view_params,
+        water_params,
+        atmosphere_params,
+        cloud_params,
+        light_params,
+        background_params,
+        ring_params,
+        post_params,
+        aa_params,
+        materials: MATERIALS,
         debug_params,
     }
 }
@@ -97,7 +258,7 @@ pub(crate) fn init_buffers(device: &wgpu::Device, params: &Params) -> Buffers {
         device,
         &wgpu::util::BufferInitDescriptor {
             label: Some("Terrain Parameters Storage Buffer"),
-            contents: bytemuck::cast_slice(&[params.terrain_params.octaves]),
+            contents: bytemuck::cast_slice(&[params.terrain_params]),
             usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
         },
     );
@@ -106,51 +267,112 @@ pub(crate) fn init_buffers(device: &wgpu::Device, params: &Params) -> Buffers {
         device,
         &wgpu::util::BufferInitDescriptor {
             label: Some("Ray Marching Parameters Storage Buffer"),
-            contents: bytemuck::cast_slice(&[
-                params.ray_params.epsilon,
-                params.ray_params.max_dist,
-                params.ray_params.max_steps,
-            ]),
+            contents: bytemuck::cast_slice(&[params.ray_params]),
             usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
         },
     );
 
-    let view_params = wgpu::util::DeviceExt::create_buffer_init(
+    let camera_params = wgpu::util::DeviceExt::create_buffer_init(
         device,
         &wgpu::util::BufferInitDescriptor {
-            label: Some("Ray Marching Parameters Storage Buffer"),
+            label: Some("Camera Parameters Storage Buffer"),
             contents: bytemuck::cast_slice(&[
-                params.view_params.x_shift,
-                params.view_params.y_shift,
-                params.view_params.zoom,
-                params.view_params.x_rot,
-                params.view_params.y_rot,
-                params.view_params.time_modifier,
-                params.view_params.fov_degrees,
+                Camera::from_view(&params.view_params).params(&params.view_params)
             ]),
             usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
         },
     );
 
-    let planet_tex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-        label: Some("Planet Tex Readback Buffer"),
-        size: PLANET_TEX_BUF_SIZE as wgpu::BufferAddress,
-        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
-        mapped_at_creation: false,
-    });
+    let water_params = wgpu::util::DeviceExt::create_buffer_init(
+        device,
+        &wgpu::util::BufferInitDescriptor {
+            label: Some("Water Parameters Storage Buffer"),
+            contents: bytemuck::cast_slice(&[params.water_params]),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        },
+    );
+
+    let atmosphere_params = wgpu::util::DeviceExt::create_buffer_init(
+        device,
+        &wgpu::util::BufferInitDescriptor {
+            label: Some("Atmosphere Parameters Storage Buffer"),
+            contents: bytemuck::cast_slice(&[params.atmosphere_params]),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        },
+    );
+
+    let cloud_params = wgpu::util::DeviceExt::create_buffer_init(
+        device,
+        &wgpu::util::BufferInitDescriptor {
+            label: Some("Cloud Parameters Storage Buffer"),
+            contents: bytemuck::cast_slice(&[params.cloud_params]),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        },
+    );
+
+    let light_params = wgpu::util::DeviceExt::create_buffer_init(
+        device,
+        &wgpu::util::BufferInitDescriptor {
+            label: Some("Light Parameters Storage Buffer"),
+            contents: bytemuck::cast_slice(&[params.light_params]),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        },
+    );
+
+    let background_params = wgpu::util::DeviceExt::create_buffer_init(
+        device,
+        &wgpu::util::BufferInitDescriptor {
+            label: Some("Background Parameters Storage Buffer"),
+            contents: bytemuck::cast_slice(&[params.background_params]),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        },
+    );
+
+    let ring_params = wgpu::util::DeviceExt::create_buffer_init(
+        device,
+        &wgpu::util::BufferInitDescriptor {
+            label: Some("Ring Parameters Storage Buffer"),
+            contents: bytemuck::cast_slice(&[params.ring_params]),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        },
+    );
+
+    let post_params = wgpu::util::DeviceExt::create_buffer_init(
+        device,
+        &wgpu::util::BufferInitDescriptor {
+            label: Some("Post Process Parameters Storage Buffer"),
+            contents: bytemuck::cast_slice(&[params.post_params]),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        },
+    );
+
+    let aa_params = wgpu::util::DeviceExt::create_buffer_init(
+        device,
+        &wgpu::util::BufferInitDescriptor {
+            label: Some("Anti-Aliasing Parameters Storage Buffer"),
+            contents: bytemuck::cast_slice(&[params.aa_params]),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        },
+    );
+
+    let materials = wgpu::util::DeviceExt::create_buffer_init(
+        device,
+        &wgpu::util::BufferInitDescriptor {
+            label: Some("Materials Storage Buffer"),
+            contents: bytemuck::cast_slice(&params.materials),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        },
+    );
 
     let debug_params = wgpu::util::DeviceExt::create_buffer_init(
         device,
         &wgpu::util::BufferInitDescriptor {
-            label: Some("Ray Marching Parameters Storage Buffer"),
-            contents: bytemuck::cast_slice(&[
-                params.debug_params.pole_start,
-                params.debug_params.pole_scale,
-            ]),
+            label: Some("Debug Parameters Storage Buffer"),
+            contents: bytemuck::cast_slice(&[params.debug_params]),
             usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
         },
     );
-    // STORAGE/CPU-READABLE BUFFER PAIRS
+    // DEBUG STORAGE BUFFERS, read back through the readback queue
     let generic_debug = device.create_buffer(&wgpu::BufferDescriptor {
         label: Some("Debug Shaders Buffer"),
         size: (std::mem::size_of::<[f32; 4]>()) as wgpu::BufferAddress,
@@ -160,13 +382,6 @@ pub(crate) fn init_buffers(device: &wgpu::Device, params: &Params) -> Buffers {
         mapped_at_creation: false,
     });
 
-    let cpu_read_generic_debug = device.create_buffer(&wgpu::BufferDescriptor {
-        label: Some("CPU Readable Buffer - Debug Shaders"),
-        size: (std::mem::size_of::<[f32; 4]>()) as wgpu::BufferAddress,
-        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
-        mapped_at_creation: false,
-    });
-
     let debug_array1 = device.create_buffer(&wgpu::BufferDescriptor {
         label: Some("Debug Shaders Buffer 1"),
         size: (std::mem::size_of::<[[f32; 4]; 512]>()) as wgpu::BufferAddress,
@@ -176,13 +391,6 @@ pub(crate) fn init_buffers(device: &wgpu::Device, params: &Params) -> Buffers {
         mapped_at_creation: false,
     });
 
-    let cpu_read_debug_array1 = device.create_buffer(&wgpu::BufferDescriptor {
-        label: Some("CPU Readable Buffer 1 - Debug Shaders"),
-        size: (std::mem::size_of::<[[f32; 4]; 512]>()) as wgpu::BufferAddress,
-        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
-        mapped_at_creation: false,
-    });
-
     let debug_array2 = device.create_buffer(&wgpu::BufferDescriptor {
         label: Some("Debug Shaders Buffer 2"),
         size: (std::mem::size_of::<[[f32; 4]; 512]>()) as wgpu::BufferAddress,
@@ -192,27 +400,65 @@ pub(crate) fn init_buffers(device: &wgpu::Device, params: &Params) -> Buffers {
         mapped_at_creation: false,
     });
 
-    let cpu_read_debug_array2 = device.create_buffer(&wgpu::BufferDescriptor {
-        label: Some("CPU Readable Buffer 2 - Debug Shaders"),
-        size: (std::mem::size_of::<[[f32; 4]; 512]>()) as wgpu::BufferAddress,
-        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+    // WAVE DIRECTION BUFFERS
+    // Nearest coast texel index for every planet texel
+    let coast_seeds_size = (PLANET_TEXTURE_WIDTH as usize
+        * PLANET_TEXTURE_HEIGHT as usize
+        * std::mem::size_of::<u32>()) as wgpu::BufferAddress;
+
+    let coast_seeds_a = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Coast Seeds Buffer A"),
+        size: coast_seeds_size,
+        usage: wgpu::BufferUsages::STORAGE,
         mapped_at_creation: false,
     });
 
+    let coast_seeds_b = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Coast Seeds Buffer B"),
+        size: coast_seeds_size,
+        usage: wgpu::BufferUsages::STORAGE,
+        mapped_at_creation: false,
+    });
+
+    // One step per dynamic offset
+    let mut step_bytes = Vec::new();
+    for step in jump_flood_steps() {
+        let mut padded = [0u8; JUMP_FLOOD_STEP_STRIDE as usize];
+        padded[..4].copy_from_slice(bytemuck::bytes_of(&step));
+        step_bytes.extend_from_slice(&padded);
+    }
+
+    let jump_flood_steps = wgpu::util::DeviceExt::create_buffer_init(
+        device,
+        &wgpu::util::BufferInitDescriptor {
+            label: Some("Jump Flood Steps Uniform Buffer"),
+            contents: &step_bytes,
+            usage: wgpu::BufferUsages::UNIFORM,
+        },
+    );
+
     Buffers {
         vertex,
         time_uniform,
         terrain_params,
         ray_params,
-        view_params,
-        planet_tex_buffer,
+        camera_params,
+        water_params,
+        atmosphere_params,
+        cloud_params,
+        light_params,
+        background_params,
+        ring_params,
+        post_params,
+        aa_params,
+        materials,
         debug_params,
         generic_debug,
-        cpu_read_generic_debug,
         debug_array1,
-        cpu_read_debug_array1,
         debug_array2,
-        cpu_read_debug_array2,
+        coast_seeds_a,
+        coast_seeds_b,
+        jump_flood_steps,
     }
 }
 
@@ -266,6 +512,56 @@ pub(crate) fn init_bind_groups(
                 },
                 count: None,
             },
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::COMPUTE | wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: wgpu::BufferSize::new(buffers.water_params.size()),
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 3,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: wgpu::BufferSize::new(buffers.atmosphere_params.size()),
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 4,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: wgpu::BufferSize::new(buffers.cloud_params.size()),
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 5,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: wgpu::BufferSize::new(buffers.materials.size()),
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 6,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: wgpu::BufferSize::new(buffers.light_params.size()),
+                },
+                count: None,
+            },
             wgpu::BindGroupLayoutEntry {
                 binding: 7,
                 visibility: wgpu::ShaderStages::COMPUTE | wgpu::ShaderStages::FRAGMENT,
@@ -300,6 +596,46 @@ pub(crate) fn init_bind_groups(
                 },
                 count: None,
             },
+            wgpu::BindGroupLayoutEntry {
+                binding: 10,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: wgpu::BufferSize::new(buffers.background_params.size()),
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 11,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: wgpu::BufferSize::new(buffers.aa_params.size()),
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 12,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: wgpu::BufferSize::new(buffers.debug_params.size()),
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 13,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: wgpu::BufferSize::new(buffers.ring_params.size()),
+                },
+                count: None,
+            },
         ],
         label: Some("fragment_bind_group_layout"),
     });
@@ -313,7 +649,27 @@ pub(crate) fn init_bind_groups(
             },
             wgpu::BindGroupEntry {
                 binding: 1,
-                resource: buffers.view_params.as_entire_binding(),
+                resource: buffers.camera_params.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: buffers.water_params.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: buffers.atmosphere_params.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: buffers.cloud_params.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 5,
+                resource: buffers.materials.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 6,
+                resource: buffers.light_params.as_entire_binding(),
             },
             wgpu::BindGroupEntry {
                 binding: 7,
@@ -327,6 +683,22 @@ pub(crate) fn init_bind_groups(
                 binding: 9,
                 resource: buffers.generic_debug.as_entire_binding(),
             },
+            wgpu::BindGroupEntry {
+                binding: 10,
+                resource: buffers.background_params.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 11,
+                resource: buffers.aa_params.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 12,
+                resource: buffers.debug_params.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 13,
+                resource: buffers.ring_params.as_entire_binding(),
+            },
         ],
         label: Some("compute_bind_group"),
     });
@@ -481,6 +853,22 @@ pub(crate) fn init_bind_groups(
                 ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
                 count: None,
             },
+            wgpu::BindGroupLayoutEntry {
+                binding: 4,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 5,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
         ],
         label: Some("sampled_texture_bgl"),
     });
@@ -504,10 +892,98 @@ pub(crate) fn init_bind_groups(
                 binding: 3,
                 resource: wgpu::BindingResource::Sampler(&textures.moon_sampler),
             },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: wgpu::BindingResource::TextureView(&textures.env_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 5,
+                resource: wgpu::BindingResource::Sampler(&textures.env_sampler),
+            },
         ],
         label: Some("sampled_texture_bg"),
     });
 
+    let wave_bgl = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: wgpu::BufferSize::new(buffers.coast_seeds_a.size()),
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: wgpu::BufferSize::new(buffers.coast_seeds_b.size()),
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<u32>() as _),
+                },
+                count: None,
+            },
+        ],
+        label: Some("wave_bgl"),
+    });
+
+    let jump_flood_step_binding = wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+        buffer: &buffers.jump_flood_steps,
+        offset: 0,
+        size: wgpu::BufferSize::new(std::mem::size_of::<u32>() as _),
+    });
+
+    let wave_bg_ab = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout: &wave_bgl,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffers.coast_seeds_a.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: buffers.coast_seeds_b.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: jump_flood_step_binding.clone(),
+            },
+        ],
+        label: Some("wave_bg_ab"),
+    });
+
+    let wave_bg_ba = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout: &wave_bgl,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffers.coast_seeds_b.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: buffers.coast_seeds_a.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: jump_flood_step_binding,
+            },
+        ],
+        label: Some("wave_bg_ba"),
+    });
+
     BindGroups {
         uniform_bg,
         uniform_bgl,
@@ -519,6 +995,9 @@ pub(crate) fn init_bind_groups(
         texture_bgl,
         sampled_texture_bg,
         sampled_texture_bgl,
+        wave_bg_ab,
+        wave_bg_ba,
+        wave_bgl,
     }
 }
 
@@ -527,6 +1006,34 @@ pub(crate) fn init_pipelines(
     bind_groups: &BindGroups,
     shader_modules: &ShaderModules,
 ) -> Pipelines {
+    let render = create_render_pipeline(
+        device,
+        bind_groups,
+        &shader_modules.v_shader,
+        &shader_modules.f_shader,
+    );
+    let (generate_planet_terrain, generate_moon_terrain) =
+        create_terrain_pipelines(device, bind_groups, &shader_modules.generate_terrain);
+    let (init_coast_seeds, jump_flood, write_wave_directions) =
+        create_wave_pipelines(device, bind_groups, &shader_modules.wave_directions);
+
+    Pipelines {
+        render,
+        generate_planet_terrain,
+        generate_moon_terrain,
+        init_coast_seeds,
+        jump_flood,
+        write_wave_directions,
+    }
+}
+
+// The pipeline builders below are also used to rebuild pipelines from reloaded shaders
+pub(crate) fn create_render_pipeline(
+    device: &wgpu::Device,
+    bind_groups: &BindGroups,
+    v_shader: &wgpu::ShaderModule,
+    f_shader: &wgpu::ShaderModule,
+) -> wgpu::RenderPipeline {
     let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
         label: Some("Render Pipeline Layout"),
         bind_group_layouts: &[
@@ -537,11 +1044,11 @@ pub(crate) fn init_pipelines(
         push_constant_ranges: &[],
     });
 
-    let render = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
         label: Some("Render Pipeline"),
         layout: Some(&render_pipeline_layout),
         vertex: wgpu::VertexState {
-            module: &shader_modules.v_shader,
+            module: v_shader,
             entry_point: "main",
             buffers: &[wgpu::VertexBufferLayout {
                 array_stride: 8, // 2 * 4byte float
@@ -553,20 +1060,33 @@ pub(crate) fn init_pipelines(
             }],
         },
         fragment: Some(wgpu::FragmentState {
-            module: &shader_modules.f_shader,
+            module: f_shader,
             entry_point: "main",
-            targets: &[Some(wgpu::ColorTargetState {
-                format: wgpu::TextureFormat::Bgra8UnormSrgb,
-                blend: Some(wgpu::BlendState::REPLACE),
-                write_mask: wgpu::ColorWrites::ALL,
-            })],
+            targets: &[
+                Some(wgpu::ColorTargetState {
+                    format: HDR_FORMAT,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }),
+                Some(wgpu::ColorTargetState {
+                    format: MOTION_FORMAT,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                }),
+            ],
         }),
         primitive: wgpu::PrimitiveState::default(),
         depth_stencil: None,
         multisample: wgpu::MultisampleState::default(),
         multiview: None,
-    });
+    })
+}
 
+pub(crate) fn create_terrain_pipelines(
+    device: &wgpu::Device,
+    bind_groups: &BindGroups,
+    module: &wgpu::ShaderModule,
+) -> (wgpu::ComputePipeline, wgpu::ComputePipeline) {
     let compute_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
         label: Some("Compute Pipeline Layout"),
         bind_group_layouts: &[
@@ -581,25 +1101,69 @@ pub(crate) fn init_pipelines(
         device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
             label: Some("Generate Planet Terrrain Pipeline"),
             layout: Some(&compute_pipeline_layout),
-            module: &shader_modules.generate_terrain,
+            module,
             entry_point: "generate_planet_terrain_map",
         });
 
     let generate_moon_terrain = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
         label: Some("Generate Moon Terrain Pipeline"),
         layout: Some(&compute_pipeline_layout),
-        module: &shader_modules.generate_terrain,
+        module,
         entry_point: "generate_moon_terrain_map",
     });
 
-    Pipelines {
-        render,
-        generate_planet_terrain,
-        generate_moon_terrain,
-    }
+    (generate_planet_terrain, generate_moon_terrain)
 }
 
-pub(crate) fn init_textures(device: &wgpu::Device, queue: &wgpu::Queue) -> Textures {
+pub(crate) fn create_wave_pipelines(
+    device: &wgpu::Device,
+    bind_groups: &BindGroups,
+    module: &wgpu::ShaderModule,
+) -> (
+    wgpu::ComputePipeline,
+    wgpu::ComputePipeline,
+    wgpu::ComputePipeline,
+) {
+    let wave_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Wave Directions Pipeline Layout"),
+        bind_group_layouts: &[
+            &bind_groups.uniform_bgl,
+            &bind_groups.compute_bgl,
+            &bind_groups.texture_bgl,
+            &bind_groups.wave_bgl,
+        ],
+        push_constant_ranges: &[],
+    });
+
+    let init_coast_seeds = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some("Init Coast Seeds Pipeline"),
+        layout: Some(&wave_pipeline_layout),
+        module,
+        entry_point: "init_coast_seeds",
+    });
+
+    let jump_flood = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some("Jump Flood Pipeline"),
+        layout: Some(&wave_pipeline_layout),
+        module,
+        entry_point: "jump_flood",
+    });
+
+    let write_wave_directions = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some("Write Wave Directions Pipeline"),
+        layout: Some(&wave_pipeline_layout),
+        module,
+        entry_point: "write_wave_directions",
+    });
+
+    (init_coast_seeds, jump_flood, write_wave_directions)
+}
+
+pub(crate) fn init_textures(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    env_map: Option<&EnvMap>,
+) -> Textures {
     let planet_view_desc = wgpu::TextureViewDescriptor {
         label: Some("planet - View Descriptor"),
         format: Some(wgpu::TextureFormat::Rgba32Float),
@@ -692,12 +1256,64 @@ pub(crate) fn init_textures(device: &wgpu::Device, queue: &wgpu::Queue) -> Textu
         ..Default::default()
     });
 
+    // Without an HDR image a single black texel keeps the binding valid
+    let (env_extent, env_texels) = match env_map {
+        Some(env) => (
+            wgpu::Extent3d {
+                width: env.width,
+                height: env.height,
+                depth_or_array_layers: 1,
+            },
+            bytemuck::cast_slice(&env.texels),
+        ),
+        None => (
+            wgpu::Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+            bytemuck::cast_slice(&[[0.0f32; 4]]),
+        ),
+    };
+
+    let env_tex = device.create_texture_with_data(
+        queue,
+        &wgpu::TextureDescriptor {
+            label: Some("environment - Sampled Texture"),
+            size: env_extent,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba32Float,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[wgpu::TextureFormat::Rgba32Float],
+        },
+        wgpu::util::TextureDataOrder::default(),
+        env_texels,
+    );
+
+    let env_view = env_tex.create_view(&wgpu::TextureViewDescriptor::default());
+
+    // Longitude wraps around, latitude stops at the poles
+    let env_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some("environment - Sampler"),
+        address_mode_u: wgpu::AddressMode::Repeat,
+        address_mode_v: wgpu::AddressMode::ClampToEdge,
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        mipmap_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+    });
+
     Textures {
         planet_tex,
         planet_tex_extent,
+        moon_tex,
         planet_sampler,
         planet_view,
         moon_sampler,
         moon_view,
+        env_sampler,
+        env_view,
     }
 }
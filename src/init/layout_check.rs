@@ -0,0 +1,338 @@
+use crate::collections::{
+    materials::Material,
+    structs::{
+        AntiAliasParams, AtmosphereParams, BackgroundParams, CameraParams, CloudParams,
+        DebugParams, LightParams, PostParams, RayParams, RingParams, TerrainParams, TimeUniform,
+        WaterParams,
+    },
+};
+
+// Rust structs written to GPU buffers are checked against the WGSL structs of the same
+// name. Every WGSL member has to match the Rust field at the same position by name,
+// offset and size. A shader may declare fewer members than the Rust struct when it
+// only reads the leading fields.
+#[derive(Debug)]
+struct HostField {
+    name: &'static str,
+    offset: usize,
+    size: usize,
+}
+
+#[derive(Debug)]
+struct HostLayout {
+    name: &'static str,
+    size: usize,
+    fields: Vec<HostField>,
+}
+
+fn field_size<T, F>(_: impl Fn(&T) -> &F) -> usize {
+    std::mem::size_of::<F>()
+}
+
+// Fields are listed in declaration order, check_host_layout catches any that are missed
+macro_rules! host_layout {
+    ($ty:ident { $($field:ident),* $(,)? }) => {
+        HostLayout {
+            name: stringify!($ty),
+            size: std::mem::size_of::<$ty>(),
+            fields: vec![$(HostField {
+                name: stringify!($field),
+                offset: std::mem::offset_of!($ty, $field),
+                size: field_size(|v: &$ty| &v.$field),
+            }),*],
+        }
+    };
+}
+
+fn host_layouts() -> Vec<HostLayout> {
+    vec![
+        host_layout!(TimeUniform { time }),
+        host_layout!(RayParams {
+            epsilon,
+            max_dist,
+            max_steps,
+            accelerate,
+            relaxation,
+            gradient_bound,
+        }),
+        host_layout!(TerrainParams { octaves, seed }),
+        host_layout!(DebugParams {
+            view,
+            heightmap_channel,
+            step_range,
+        }),
+        host_layout!(CameraParams {
+            position_x,
+            position_y,
+            position_z,
+            fov_y,
+            rotation_x,
+            rotation_y,
+            rotation_z,
+            rotation_w,
+            near,
+            far,
+        }),
+        host_layout!(WaterParams {
+            wave_amplitude,
+            wave_speed,
+            wave_frequency,
+            shoaling,
+            foam_width,
+        }),
+        host_layout!(AtmosphereParams {
+            outer_radius,
+            density,
+            rayleigh_scale_height,
+            mie_scale_height,
+            rayleigh_r,
+            rayleigh_g,
+            rayleigh_b,
+            mie_coefficient,
+            mie_g,
+            sun_intensity,
+        }),
+        host_layout!(Material {
+            albedo,
+            roughness,
+            metallic,
+            reflectance,
+            _padding,
+        }),
+        host_layout!(LightParams {
+            sun_azimuth,
+            sun_elevation,
+            sun_distance,
+            orbit_speed,
+            sun_r,
+            sun_g,
+            sun_b,
+            sun_intensity,
+            angular_radius,
+            fill_enabled,
+            fill_azimuth,
+            fill_elevation,
+            fill_r,
+            fill_g,
+            fill_b,
+            fill_intensity,
+        }),
+        host_layout!(CloudParams {
+            enabled,
+            coverage,
+            altitude,
+            thickness,
+            wind_speed,
+            density,
+            noise_scale,
+        }),
+        host_layout!(BackgroundParams {
+            star_scale,
+            star_density,
+            star_brightness,
+            nebula_enabled,
+            nebula_intensity,
+            sun_disc_intensity,
+            env_map_enabled,
+            env_intensity,
+            env_rotation,
+        }),
+        host_layout!(RingParams {
+            enabled,
+            inner_radius,
+            outer_radius,
+            tilt,
+            opacity,
+            band_frequency,
+            scatter_g,
+            inner_r,
+            inner_g,
+            inner_b,
+            outer_r,
+            outer_g,
+            outer_b,
+        }),
+        host_layout!(PostParams {
+            exposure_enabled,
+            exposure,
+            tonemapper,
+            bloom_enabled,
+            bloom_threshold,
+            bloom_intensity,
+            srgb_enabled,
+        }),
+        host_layout!(AntiAliasParams {
+            mode,
+            ssaa_samples,
+            taa_blend,
+            history_valid,
+            jitter_x,
+            jitter_y,
+            frame_index,
+            prev_time,
+            prev_position_x,
+            prev_position_y,
+            prev_position_z,
+            prev_fov_y,
+            prev_rotation_x,
+            prev_rotation_y,
+            prev_rotation_z,
+            prev_rotation_w,
+        }),
+    ]
+}
+
+// The listed fields have to cover the struct back to back, so a field left out of
+// host_layouts or listed out of order shows up as a gap
+fn check_host_layout(host: &HostLayout, errors: &mut Vec<String>) {
+    let mut end = 0;
+    for field in &host.fields {
+        if field.offset != end {
+            errors.push(format!(
+                "{}.{} is at offset {} but the previous field ends at {}, is a field missing or out of order in host_layouts?",
+                host.name, field.name, field.offset, end
+            ));
+        }
+        end = field.offset + field.size;
+    }
+
+    if end != host.size {
+        errors.push(format!(
+            "{} is {} bytes but its listed fields end at {}",
+            host.name, host.size, end
+        ));
+    }
+}
+
+// Compares every WGSL struct in the module that has a Rust counterpart
+fn check_module(
+    shader: &str,
+    module: &naga::Module,
+    errors: &mut Vec<String>,
+) -> Vec<&'static str> {
+    let mut layouter = naga::proc::Layouter::default();
+    if let Err(e) = layouter.update(module.to_ctx()) {
+        errors.push(format!("{}: {}", shader, e));
+        return Vec::new();
+    }
+
+    let mut found = Vec::new();
+    for host in host_layouts() {
+        let Some((_, ty)) = module
+            .types
+            .iter()
+            .find(|(_, ty)| ty.name.as_deref() == Some(host.name))
+        else {
+            continue;
+        };
+        let naga::TypeInner::Struct { members, span } = &ty.inner else {
+            continue;
+        };
+        found.push(host.name);
+
+        if members.len() > host.fields.len() {
+            errors.push(format!(
+                "{}: {} has {} members but the Rust struct only has {} fields",
+                shader,
+                host.name,
+                members.len(),
+                host.fields.len()
+            ));
+        }
+
+        for (member, field) in members.iter().zip(&host.fields) {
+            let member_name = member.name.as_deref().unwrap_or("");
+            let member_size = layouter[member.ty].size as usize;
+
+            if member_name != field.name
+                || member.offset as usize != field.offset
+                || member_size != field.size
+            {
+                errors.push(format!(
+                    "{}: {}.{} (offset {}, size {}) doesn't match Rust {}.{} (offset {}, size {})",
+                    shader,
+                    host.name,
+                    member_name,
+                    member.offset,
+                    member_size,
+                    host.name,
+                    field.name,
+                    field.offset,
+                    field.size
+                ));
+            }
+        }
+
+        // Buffers are sized from the Rust struct, the shader can't read past it
+        if *span as usize > host.size {
+            errors.push(format!(
+                "{}: {} is {} bytes in WGSL but only {} in Rust",
+                shader, host.name, span, host.size
+            ));
+        }
+    }
+
+    found
+}
+
+// Used when a reloaded shader is about to replace a running one
+pub(crate) fn check_shader_layouts(shader: &str, module: &naga::Module) -> Result<(), String> {
+    let mut errors = Vec::new();
+    check_module(shader, module, &mut errors);
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.join("\n"))
+    }
+}
+
+// Every Rust struct also has to be declared by at least one shader, which catches renames
+pub(crate) fn check_all_layouts(shaders: &[(&str, naga::Module)]) -> Result<(), String> {
+    let mut errors = Vec::new();
+    let hosts = host_layouts();
+
+    for host in &hosts {
+        check_host_layout(host, &mut errors);
+    }
+
+    let mut found = Vec::new();
+    for (shader, module) in shaders {
+        found.extend(check_module(shader, module, &mut errors));
+    }
+
+    for host in &hosts {
+        if !found.contains(&host.name) {
+            errors.push(format!("{} isn't declared by any shader", host.name));
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::check_all_layouts;
+    use crate::init::shader_compose::{compose_shader, embedded_source, ShaderFile};
+
+    #[test]
+    fn host_structs_match_shaders() {
+        let shaders: Vec<_> = ShaderFile::ALL
+            .iter()
+            .map(|&file| {
+                let composed = compose_shader(file, &embedded_source).unwrap();
+                let module = naga::front::wgsl::parse_str(&composed.source)
+                    .unwrap_or_else(|e| panic!("{}", e.emit_to_string(&composed.source)));
+                (file.name(), module)
+            })
+            .collect();
+
+        if let Err(errors) = check_all_layouts(&shaders) {
+            panic!("\n{}", errors);
+        }
+    }
+}
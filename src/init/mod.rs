@@ -1 +1,5 @@
+pub(crate) mod env_map;
 pub(crate) mod init_functions;
+pub(crate) mod layout_check;
+pub(crate) mod param_files;
+pub(crate) mod shader_compose;
@@ -0,0 +1,62 @@
+use std::path::{Path, PathBuf};
+
+use crate::collections::structs::{CameraPath, Params};
+
+// Writes every parameter set, including lights and materials, as one JSON file
+pub(crate) fn save_params(params: &Params, dir: &Path) -> std::io::Result<PathBuf> {
+    std::fs::create_dir_all(dir)?;
+
+    let stamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    let path = dir.join(format!("params_{stamp}.json"));
+    let json = serde_json::to_string_pretty(params).map_err(std::io::Error::other)?;
+    std::fs::write(&path, json)?;
+
+    Ok(path)
+}
+
+pub(crate) fn load_params(path: &Path) -> std::io::Result<Params> {
+    let json = std::fs::read_to_string(path)?;
+    let params = serde_json::from_str(&json)?;
+
+    Ok(params)
+}
+
+pub(crate) fn save_camera_path(path: &CameraPath, dir: &Path) -> std::io::Result<PathBuf> {
+    std::fs::create_dir_all(dir)?;
+
+    let stamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    let file = dir.join(format!("camera_path_{stamp}.json"));
+    let json = serde_json::to_string_pretty(path).map_err(std::io::Error::other)?;
+    std::fs::write(&file, json)?;
+
+    Ok(file)
+}
+
+pub(crate) fn load_camera_path(file: &Path) -> std::io::Result<CameraPath> {
+    let json = std::fs::read_to_string(file)?;
+    let path = serde_json::from_str(&json)?;
+
+    Ok(path)
+}
+
+// Timestamped names sort in save order
+pub(crate) fn latest_camera_path(dir: &Path) -> std::io::Result<Option<PathBuf>> {
+    let latest = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|p| {
+            p.file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.starts_with("camera_path_") && n.ends_with(".json"))
+        })
+        .max();
+
+    Ok(latest)
+}
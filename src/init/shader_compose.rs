@@ -0,0 +1,277 @@
+use crate::collections::consts::{
+    AA_OFF, AA_SSAA, AA_TAA, DEBUG_VIEW_HEIGHTMAP, DEBUG_VIEW_MATERIAL, DEBUG_VIEW_NORMALS,
+    DEBUG_VIEW_OFF, DEBUG_VIEW_OVERSHOOT, DEBUG_VIEW_SLOPE, DEBUG_VIEW_STEPS, DEBUG_VIEW_TRIPLANAR,
+    DEBUG_VIEW_WATER_DEPTH, DEBUG_VIEW_WAVE_DIRECTION, ICE_LEVEL, MOON_ORBIT_INCLINATION,
+    MOON_ORBIT_RADIUS, MOON_ORBIT_SPEED, MOON_RADIUS, MOON_TEXTURE_HEIGHT, MOON_TEXTURE_WIDTH,
+    PLANET_RADIUS, PLANET_ROTATION, PLANET_TERRAIN_AMP, PLANET_TEXTURE_HEIGHT,
+    PLANET_TEXTURE_WIDTH, SCREEN_HEIGHT, SCREEN_WIDTH, WATER_LEVEL,
+};
+
+// Shaders share WGSL through `#include "path"` lines, paths are relative to src/shaders.
+// Every shader also starts with the constants below, so the Rust side is the only
+// place they are defined.
+const INCLUDE_DIRECTIVE: &str = "#include";
+// Origin given to the injected constants in error messages
+const HOST_CONSTS_ORIGIN: &str = "consts.rs";
+
+// Top level shaders, each is built into its own module
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum ShaderFile {
+    Vertex,
+    Fragment,
+    GenerateTerrain,
+    WaveDirections,
+    Post,
+}
+
+impl ShaderFile {
+    pub(crate) const ALL: [ShaderFile; 5] = [
+        ShaderFile::Vertex,
+        ShaderFile::Fragment,
+        ShaderFile::GenerateTerrain,
+        ShaderFile::WaveDirections,
+        ShaderFile::Post,
+    ];
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            ShaderFile::Vertex => "v2.wgsl",
+            ShaderFile::Fragment => "frag.wgsl",
+            ShaderFile::GenerateTerrain => "compute/generate_terrain.wgsl",
+            ShaderFile::WaveDirections => "compute/wave_directions.wgsl",
+            ShaderFile::Post => "post.wgsl",
+        }
+    }
+
+    pub(crate) fn label(self) -> &'static str {
+        match self {
+            ShaderFile::Vertex => "Vertex Shader",
+            ShaderFile::Fragment => "Fragment Shader",
+            ShaderFile::GenerateTerrain => "Generate Terrain Shader",
+            ShaderFile::WaveDirections => "Wave Directions Shader",
+            ShaderFile::Post => "Post Process Shader",
+        }
+    }
+}
+
+// Copies built into the binary, used at startup so it runs from any directory
+pub(crate) fn embedded_source(name: &str) -> Result<String, String> {
+    let source = match name {
+        "v2.wgsl" => include_str!("../shaders/v2.wgsl"),
+        "frag.wgsl" => include_str!("../shaders/frag.wgsl"),
+        "post.wgsl" => include_str!("../shaders/post.wgsl"),
+        "compute/generate_terrain.wgsl" => include_str!("../shaders/compute/generate_terrain.wgsl"),
+        "compute/wave_directions.wgsl" => include_str!("../shaders/compute/wave_directions.wgsl"),
+        "common/math.wgsl" => include_str!("../shaders/common/math.wgsl"),
+        "common/time.wgsl" => include_str!("../shaders/common/time.wgsl"),
+        _ => return Err(format!("{} is not embedded in the binary", name)),
+    };
+
+    Ok(source.to_string())
+}
+
+#[derive(Debug)]
+pub(crate) struct ComposedShader {
+    pub(crate) source: String,
+    // Every file that went into the source, the shader itself first
+    pub(crate) files: Vec<String>,
+    // (file, 1-based line) each line of the source came from
+    lines: Vec<(String, u32)>,
+}
+
+impl ComposedShader {
+    // Maps a 1-based line of the composed source back to the file it was written in
+    pub(crate) fn origin(&self, line: u32) -> (&str, u32) {
+        match self.lines.get(line.saturating_sub(1) as usize) {
+            Some((file, line)) => (file, *line),
+            None => (&self.files[0], line),
+        }
+    }
+}
+
+pub(crate) fn compose_shader(
+    file: ShaderFile,
+    load: &dyn Fn(&str) -> Result<String, String>,
+) -> Result<ComposedShader, String> {
+    let mut composed = ComposedShader {
+        source: String::new(),
+        files: Vec::new(),
+        lines: Vec::new(),
+    };
+
+    for (i, line) in host_consts().lines().enumerate() {
+        push_line(&mut composed, line, HOST_CONSTS_ORIGIN, i as u32 + 1);
+    }
+
+    append_file(&mut composed, file.name(), load)?;
+
+    Ok(composed)
+}
+
+// Each file is only included once, later includes of it are dropped
+fn append_file(
+    composed: &mut ComposedShader,
+    name: &str,
+    load: &dyn Fn(&str) -> Result<String, String>,
+) -> Result<(), String> {
+    composed.files.push(name.to_string());
+    let source = load(name)?;
+
+    for (i, line) in source.lines().enumerate() {
+        let line_number = i as u32 + 1;
+
+        match line.trim().strip_prefix(INCLUDE_DIRECTIVE) {
+            Some(rest) => {
+                let include = rest.trim().trim_matches('"');
+                if include.is_empty() {
+                    return Err(format!("{}:{}: #include without a path", name, line_number));
+                }
+                if !composed.files.iter().any(|f| f == include) {
+                    append_file(composed, include, load)
+                        .map_err(|e| format!("{}:{}: {}", name, line_number, e))?;
+                }
+            }
+            None => push_line(composed, line, name, line_number),
+        }
+    }
+
+    Ok(())
+}
+
+fn push_line(composed: &mut ComposedShader, line: &str, file: &str, line_number: u32) {
+    composed.source.push_str(line);
+    composed.source.push('\n');
+    composed.lines.push((file.to_string(), line_number));
+}
+
+// Rust consts the shaders need, modes are compared as u32 on the GPU
+fn host_consts() -> String {
+    let floats = [
+        ("SCREEN_WIDTH", SCREEN_WIDTH as f32),
+        ("SCREEN_HEIGHT", SCREEN_HEIGHT as f32),
+        ("PLANET_RADIUS", PLANET_RADIUS),
+        ("PLANET_ROTATION", PLANET_ROTATION),
+        ("TERRAIN_AMP", PLANET_TERRAIN_AMP),
+        ("WATER_LEVEL", WATER_LEVEL),
+        ("ICE_LEVEL", ICE_LEVEL),
+        ("MOON_RADIUS", MOON_RADIUS),
+        ("MOON_ORBIT_SPEED", MOON_ORBIT_SPEED),
+        ("MOON_ORBIT_RADIUS", MOON_ORBIT_RADIUS),
+        ("MOON_ORBIT_INCLINATION", MOON_ORBIT_INCLINATION),
+    ];
+
+    let uints = [
+        ("PLANET_TEX_WIDTH", PLANET_TEXTURE_WIDTH),
+        ("PLANET_TEX_HEIGHT", PLANET_TEXTURE_HEIGHT),
+        ("MOON_TEX_WIDTH", MOON_TEXTURE_WIDTH),
+        ("MOON_TEX_HEIGHT", MOON_TEXTURE_HEIGHT),
+        ("AA_OFF", AA_OFF as u32),
+        ("AA_SSAA", AA_SSAA as u32),
+        ("AA_TAA", AA_TAA as u32),
+        ("DEBUG_VIEW_OFF", DEBUG_VIEW_OFF as u32),
+        ("DEBUG_VIEW_NORMALS", DEBUG_VIEW_NORMALS as u32),
+        ("DEBUG_VIEW_SLOPE", DEBUG_VIEW_SLOPE as u32),
+        ("DEBUG_VIEW_STEPS", DEBUG_VIEW_STEPS as u32),
+        ("DEBUG_VIEW_MATERIAL", DEBUG_VIEW_MATERIAL as u32),
+        ("DEBUG_VIEW_WATER_DEPTH", DEBUG_VIEW_WATER_DEPTH as u32),
+        ("DEBUG_VIEW_HEIGHTMAP", DEBUG_VIEW_HEIGHTMAP as u32),
+        (
+            "DEBUG_VIEW_WAVE_DIRECTION",
+            DEBUG_VIEW_WAVE_DIRECTION as u32,
+        ),
+        ("DEBUG_VIEW_TRIPLANAR", DEBUG_VIEW_TRIPLANAR as u32),
+        ("DEBUG_VIEW_OVERSHOOT", DEBUG_VIEW_OVERSHOOT as u32),
+    ];
+
+    let mut source = String::new();
+    for (name, value) in floats {
+        // Debug formatting always keeps the decimal point WGSL needs for an f32
+        source.push_str(&format!("const {}: f32 = {:?};\n", name, value));
+    }
+    for (name, value) in uints {
+        source.push_str(&format!("const {}: u32 = {}u;\n", name, value));
+    }
+
+    source
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compose_shader, host_consts, ShaderFile, HOST_CONSTS_ORIGIN};
+    use crate::collections::consts::PLANET_RADIUS;
+
+    fn load(name: &str) -> Result<String, String> {
+        let source = match name {
+            "frag.wgsl" => "#include \"common/a.wgsl\"\n#include \"common/b.wgsl\"\nfn frag() {}\n",
+            "common/a.wgsl" => "const A: f32 = 1.0;\n",
+            "common/b.wgsl" => "#include \"common/a.wgsl\"\n\nconst B: f32 = A;\n",
+            "post.wgsl" => "fn post() {}\n#include \"common/missing.wgsl\"\n",
+            "v2.wgsl" => "  #include   \n",
+            _ => return Err(format!("{} not found", name)),
+        };
+
+        Ok(source.to_string())
+    }
+
+    // 1-based line of the composed source holding `text`
+    fn line_of(source: &str, text: &str) -> u32 {
+        source.lines().position(|l| l == text).unwrap() as u32 + 1
+    }
+
+    #[test]
+    fn files_are_included_once() {
+        let composed = compose_shader(ShaderFile::Fragment, &load).unwrap();
+
+        assert_eq!(composed.source.matches("const A: f32 = 1.0;").count(), 1);
+        assert!(!composed.source.contains("#include"));
+        assert_eq!(
+            composed.files,
+            vec!["frag.wgsl", "common/a.wgsl", "common/b.wgsl"]
+        );
+    }
+
+    #[test]
+    fn host_consts_come_first() {
+        let composed = compose_shader(ShaderFile::Fragment, &load).unwrap();
+
+        assert!(composed.source.starts_with(&host_consts()));
+        assert!(composed
+            .source
+            .contains(&format!("const PLANET_RADIUS: f32 = {:?};", PLANET_RADIUS)));
+        assert!(composed.source.contains("const AA_TAA: u32 = "));
+        assert_eq!(composed.origin(1), (HOST_CONSTS_ORIGIN, 1));
+    }
+
+    #[test]
+    fn origin_maps_back_to_the_written_line() {
+        let composed = compose_shader(ShaderFile::Fragment, &load).unwrap();
+        let source = &composed.source;
+
+        assert_eq!(
+            composed.origin(line_of(source, "const A: f32 = 1.0;")),
+            ("common/a.wgsl", 1)
+        );
+        assert_eq!(
+            composed.origin(line_of(source, "const B: f32 = A;")),
+            ("common/b.wgsl", 3)
+        );
+        assert_eq!(
+            composed.origin(line_of(source, "fn frag() {}")),
+            ("frag.wgsl", 3)
+        );
+    }
+
+    #[test]
+    fn missing_include_names_the_including_line() {
+        let error = compose_shader(ShaderFile::Post, &load).unwrap_err();
+
+        assert!(error.starts_with("post.wgsl:2: "), "{}", error);
+        assert!(error.contains("common/missing.wgsl not found"), "{}", error);
+    }
+
+    #[test]
+    fn include_without_a_path_is_an_error() {
+        let error = compose_shader(ShaderFile::Vertex, &load).unwrap_err();
+        assert_eq!(error, "v2.wgsl:1: #include without a path");
+    }
+}
@@ -1,9 +1,16 @@
+mod analysis;
+mod camera;
 mod init;
+mod post;
+mod readback;
+mod reload;
 mod state;
 mod updates;
 use state::app_state::State;
 mod collections;
-use collections::consts::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use collections::consts::{
+    CAMERA_PATH_VAR, ENV_MAP_VAR, SCREEN_HEIGHT, SCREEN_WIDTH, WINDOW_TITLE,
+};
 
 use winit::{
     dpi::PhysicalSize,
@@ -20,16 +27,27 @@ fn main() {
     event_loop.set_control_flow(ControlFlow::Poll);
 
     let window = WindowBuilder::new()
-        .with_title("winit window")
+        .with_title(WINDOW_TITLE)
         .with_inner_size(PhysicalSize::new(SCREEN_WIDTH, SCREEN_HEIGHT))
         .build(&event_loop)
         .expect("window should open");
 
-    let mut state = futures::executor::block_on(State::new(window.into()));
+    // Optional parameter file saved from PRINT mode
+    let params_path = std::env::args().nth(1).map(std::path::PathBuf::from);
+    // Optional equirectangular .hdr image replacing the procedural stars
+    let env_map_path = std::env::var_os(ENV_MAP_VAR).map(std::path::PathBuf::from);
+    let mut state =
+        futures::executor::block_on(State::new(window.into(), params_path, env_map_path));
+
+    // Loaded before the terrain is generated, so it's generated with the path's seed
+    if let Some(path) = std::env::var_os(CAMERA_PATH_VAR).map(std::path::PathBuf::from) {
+        match state.load_camera_path(&path) {
+            Ok(_) => state.play_camera_path(),
+            Err(e) => eprintln!("Error loading camera path {}: {:?}", path.display(), e),
+        }
+    }
 
-    state.init_planet_terrain();
-    state.init_moon_terrain();
-    state.calculate_wave_dir();
+    state.start_terrain_generation();
 
     event_loop
         .run(move |event, elwt| match event {
@@ -0,0 +1,454 @@
+use crate::collections::{
+    consts::{HDR_FORMAT, MOTION_FORMAT},
+    structs::Buffers,
+};
+
+// Bright areas are blurred at a fraction of the screen resolution
+const BLOOM_DOWNSCALE: u32 = 2;
+
+#[derive(Debug)]
+struct PostTargets {
+    hdr_view: wgpu::TextureView,
+    motion_view: wgpu::TextureView,
+    // TAA output, copied into the history for the next frame
+    resolved: wgpu::Texture,
+    resolved_view: wgpu::TextureView,
+    history: wgpu::Texture,
+    // Bloom ping-pong, bright -> a, a -> b (horizontal), b -> a (vertical)
+    bloom_a_view: wgpu::TextureView,
+    bloom_b_view: wgpu::TextureView,
+    taa_bg: wgpu::BindGroup,
+    bright_bg: wgpu::BindGroup,
+    blur_h_bg: wgpu::BindGroup,
+    blur_v_bg: wgpu::BindGroup,
+    composite_bg: wgpu::BindGroup,
+}
+
+// Rebuilt together when post.wgsl is reloaded
+#[derive(Debug)]
+pub(crate) struct PostPipelines {
+    taa: wgpu::RenderPipeline,
+    bright: wgpu::RenderPipeline,
+    blur_h: wgpu::RenderPipeline,
+    blur_v: wgpu::RenderPipeline,
+    composite: wgpu::RenderPipeline,
+}
+
+// The scene is rendered into an HDR target, then anti-aliased, bloomed,
+// exposed, tonemapped and encoded to sRGB on the way to the swapchain
+#[derive(Debug)]
+pub(crate) struct PostProcess {
+    bgl: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    output_format: wgpu::TextureFormat,
+    pipelines: PostPipelines,
+    targets: PostTargets,
+}
+
+fn create_target(
+    device: &wgpu::Device,
+    width: u32,
+    height: u32,
+    format: wgpu::TextureFormat,
+    usage: wgpu::TextureUsages,
+    label: &str,
+) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d {
+            width: width.max(1),
+            height: height.max(1),
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: usage | wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    })
+}
+
+fn create_view(texture: &wgpu::Texture) -> wgpu::TextureView {
+    texture.create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+fn create_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    module: &wgpu::ShaderModule,
+    entry_point: &str,
+    format: wgpu::TextureFormat,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(entry_point),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module,
+            entry_point: "vs_main",
+            buffers: &[],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module,
+            entry_point,
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::REPLACE),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+    })
+}
+
+fn run_pass(
+    encoder: &mut wgpu::CommandEncoder,
+    label: &str,
+    target: &wgpu::TextureView,
+    pipeline: &wgpu::RenderPipeline,
+    bind_group: &wgpu::BindGroup,
+) {
+    let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some(label),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view: target,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                store: wgpu::StoreOp::Store,
+            },
+        })],
+        ..Default::default()
+    });
+
+    pass.set_pipeline(pipeline);
+    pass.set_bind_group(0, bind_group, &[]);
+    pass.draw(0..3, 0..1);
+}
+
+impl PostProcess {
+    // output_format is the swapchain format without its sRGB suffix
+    pub(crate) fn new(
+        device: &wgpu::Device,
+        module: &wgpu::ShaderModule,
+        buffers: &Buffers,
+        output_format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+
+        let buffer_entry = |binding, buffer: &wgpu::Buffer| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: wgpu::BufferSize::new(buffer.size()),
+            },
+            count: None,
+        };
+
+        let bgl = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                buffer_entry(0, &buffers.post_params),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                texture_entry(2),
+                texture_entry(3),
+                texture_entry(4),
+                texture_entry(5),
+                buffer_entry(6, &buffers.aa_params),
+            ],
+            label: Some("post_bgl"),
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("post - Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let pipelines = Self::build_pipelines(device, &bgl, module, output_format);
+        let targets = Self::create_targets(device, &bgl, &sampler, buffers, width, height);
+
+        Self {
+            bgl,
+            sampler,
+            output_format,
+            pipelines,
+            targets,
+        }
+    }
+
+    fn build_pipelines(
+        device: &wgpu::Device,
+        bgl: &wgpu::BindGroupLayout,
+        module: &wgpu::ShaderModule,
+        output_format: wgpu::TextureFormat,
+    ) -> PostPipelines {
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Post Process Pipeline Layout"),
+            bind_group_layouts: &[bgl],
+            push_constant_ranges: &[],
+        });
+
+        PostPipelines {
+            taa: create_pipeline(device, &layout, module, "fs_taa", HDR_FORMAT),
+            bright: create_pipeline(device, &layout, module, "fs_bright", HDR_FORMAT),
+            blur_h: create_pipeline(device, &layout, module, "fs_blur_h", HDR_FORMAT),
+            blur_v: create_pipeline(device, &layout, module, "fs_blur_v", HDR_FORMAT),
+            composite: create_pipeline(device, &layout, module, "fs_composite", output_format),
+        }
+    }
+
+    // Built from a reloaded post.wgsl, swapped in once they are known to be valid
+    pub(crate) fn create_pipelines(
+        &self,
+        device: &wgpu::Device,
+        module: &wgpu::ShaderModule,
+    ) -> PostPipelines {
+        Self::build_pipelines(device, &self.bgl, module, self.output_format)
+    }
+
+    pub(crate) fn set_pipelines(&mut self, pipelines: PostPipelines) {
+        self.pipelines = pipelines;
+    }
+
+    fn create_targets(
+        device: &wgpu::Device,
+        bgl: &wgpu::BindGroupLayout,
+        sampler: &wgpu::Sampler,
+        buffers: &Buffers,
+        width: u32,
+        height: u32,
+    ) -> PostTargets {
+        let target = wgpu::TextureUsages::RENDER_ATTACHMENT;
+        let bloom_width = width / BLOOM_DOWNSCALE;
+        let bloom_height = height / BLOOM_DOWNSCALE;
+
+        let hdr = create_target(device, width, height, HDR_FORMAT, target, "hdr - Target");
+        let motion = create_target(
+            device,
+            width,
+            height,
+            MOTION_FORMAT,
+            target,
+            "motion - Target",
+        );
+        let resolved = create_target(
+            device,
+            width,
+            height,
+            HDR_FORMAT,
+            target | wgpu::TextureUsages::COPY_SRC,
+            "taa resolved - Target",
+        );
+        let history = create_target(
+            device,
+            width,
+            height,
+            HDR_FORMAT,
+            wgpu::TextureUsages::COPY_DST,
+            "taa history - Texture",
+        );
+        let bloom_a = create_target(
+            device,
+            bloom_width,
+            bloom_height,
+            HDR_FORMAT,
+            target,
+            "bloom a - Target",
+        );
+        let bloom_b = create_target(
+            device,
+            bloom_width,
+            bloom_height,
+            HDR_FORMAT,
+            target,
+            "bloom b - Target",
+        );
+
+        let hdr_view = create_view(&hdr);
+        let motion_view = create_view(&motion);
+        let resolved_view = create_view(&resolved);
+        let history_view = create_view(&history);
+        let bloom_a_view = create_view(&bloom_a);
+        let bloom_b_view = create_view(&bloom_b);
+
+        let bind_group = |label, source: &wgpu::TextureView, bloom: &wgpu::TextureView| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: bgl,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: buffers.post_params.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::TextureView(source),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: wgpu::BindingResource::TextureView(bloom),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 4,
+                        resource: wgpu::BindingResource::TextureView(&history_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 5,
+                        resource: wgpu::BindingResource::TextureView(&motion_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 6,
+                        resource: buffers.aa_params.as_entire_binding(),
+                    },
+                ],
+                label: Some(label),
+            })
+        };
+
+        // The unused bloom slot must still differ from the pass's render target
+        let taa_bg = bind_group("post_taa_bg", &hdr_view, &bloom_a_view);
+        let bright_bg = bind_group("post_bright_bg", &resolved_view, &bloom_b_view);
+        let blur_h_bg = bind_group("post_blur_h_bg", &bloom_a_view, &resolved_view);
+        let blur_v_bg = bind_group("post_blur_v_bg", &bloom_b_view, &resolved_view);
+        let composite_bg = bind_group("post_composite_bg", &resolved_view, &bloom_a_view);
+
+        PostTargets {
+            hdr_view,
+            motion_view,
+            resolved,
+            resolved_view,
+            history,
+            bloom_a_view,
+            bloom_b_view,
+            taa_bg,
+            bright_bg,
+            blur_h_bg,
+            blur_v_bg,
+            composite_bg,
+        }
+    }
+
+    pub(crate) fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        buffers: &Buffers,
+        width: u32,
+        height: u32,
+    ) {
+        self.targets =
+            Self::create_targets(device, &self.bgl, &self.sampler, buffers, width, height);
+    }
+
+    // The scene pass renders into these instead of the swapchain
+    pub(crate) fn hdr_view(&self) -> &wgpu::TextureView {
+        &self.targets.hdr_view
+    }
+
+    pub(crate) fn motion_view(&self) -> &wgpu::TextureView {
+        &self.targets.motion_view
+    }
+
+    pub(crate) fn run(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        output: &wgpu::TextureView,
+        bloom_enabled: bool,
+        taa_enabled: bool,
+    ) {
+        let t = &self.targets;
+        let p = &self.pipelines;
+
+        // Passes the frame straight through unless TAA is on
+        run_pass(
+            encoder,
+            "TAA Resolve Pass",
+            &t.resolved_view,
+            &p.taa,
+            &t.taa_bg,
+        );
+
+        if taa_enabled {
+            encoder.copy_texture_to_texture(
+                t.resolved.as_image_copy(),
+                t.history.as_image_copy(),
+                t.resolved.size(),
+            );
+        }
+
+        if bloom_enabled {
+            run_pass(
+                encoder,
+                "Bloom Bright Pass",
+                &t.bloom_a_view,
+                &p.bright,
+                &t.bright_bg,
+            );
+            run_pass(
+                encoder,
+                "Bloom Blur H Pass",
+                &t.bloom_b_view,
+                &p.blur_h,
+                &t.blur_h_bg,
+            );
+            run_pass(
+                encoder,
+                "Bloom Blur V Pass",
+                &t.bloom_a_view,
+                &p.blur_v,
+                &t.blur_v_bg,
+            );
+        }
+
+        run_pass(
+            encoder,
+            "Composite Pass",
+            output,
+            &p.composite,
+            &t.composite_bg,
+        );
+    }
+}
+
+fn halton(mut index: u32, base: u32) -> f32 {
+    let mut f = 1.0;
+    let mut r = 0.0;
+
+    while index > 0 {
+        f /= base as f32;
+        r += f * (index % base) as f32;
+        index /= base;
+    }
+
+    r
+}
+
+// Sub-pixel offset in pixels for a TAA frame, cycles through 8 Halton(2, 3) points
+pub(crate) fn taa_jitter(frame: u32) -> [f32; 2] {
+    let i = frame % 8 + 1;
+    [halton(i, 2) - 0.5, halton(i, 3) - 0.5]
+}
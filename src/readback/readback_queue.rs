@@ -0,0 +1,264 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::channel::oneshot;
+use futures::executor::LocalPool;
+use futures::task::LocalSpawnExt;
+
+// wgpu requires texture copy rows to be padded to 256 bytes
+const COPY_ROW_ALIGNMENT: u32 = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+
+#[derive(Debug, Clone)]
+pub(crate) enum ReadbackError {
+    Map(wgpu::BufferAsyncError),
+    // The queue was dropped before the data arrived
+    Canceled,
+}
+
+pub(crate) type ReadbackResult<T> = Result<Vec<T>, ReadbackError>;
+
+// Resolves to the typed contents of a queued readback
+pub(crate) struct Readback<T> {
+    rx: oneshot::Receiver<ReadbackResult<T>>,
+}
+
+impl<T> Readback<T> {
+    // Non-blocking check for callers polling from the frame loop
+    pub(crate) fn try_take(&mut self) -> Option<ReadbackResult<T>> {
+        match self.rx.try_recv() {
+            Ok(Some(result)) => Some(result),
+            Ok(None) => None,
+            Err(_) => Some(Err(ReadbackError::Canceled)),
+        }
+    }
+}
+
+impl<T> Future for Readback<T> {
+    type Output = ReadbackResult<T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.rx)
+            .poll(cx)
+            .map(|result| result.unwrap_or(Err(ReadbackError::Canceled)))
+    }
+}
+
+type Deliver = Box<dyn FnOnce(Result<&[u8], ReadbackError>)>;
+
+struct PendingReadback {
+    label: String,
+    staging: wgpu::Buffer,
+    mapped: oneshot::Receiver<Result<(), wgpu::BufferAsyncError>>,
+    // Strips row padding and converts the mapped bytes to the requested type
+    deliver: Deliver,
+}
+
+// Queues GPU -> CPU copies and hands the data back without stalling the
+// render loop. Every request copies into its own staging buffer, so the
+// source can keep being used while the map is pending.
+pub(crate) struct ReadbackQueue {
+    pending: Vec<PendingReadback>,
+    pool: LocalPool,
+}
+
+impl std::fmt::Debug for ReadbackQueue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ReadbackQueue")
+            .field(
+                "pending",
+                &self.pending.iter().map(|p| &p.label).collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+impl ReadbackQueue {
+    pub(crate) fn new() -> Self {
+        Self {
+            pending: Vec::new(),
+            pool: LocalPool::new(),
+        }
+    }
+
+    pub(crate) fn read_buffer<T: bytemuck::Pod>(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        source: &wgpu::Buffer,
+        label: &str,
+    ) -> Readback<T> {
+        let staging = create_staging(device, source.size(), label);
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Readback buffer copy - encoder"),
+        });
+        encoder.copy_buffer_to_buffer(source, 0, &staging, 0, source.size());
+        queue.submit(Some(encoder.finish()));
+
+        self.push(staging, label, |bytes| bytes.to_vec())
+    }
+
+    // Reads mip 0 of a 2D texture, rows are returned tightly packed
+    pub(crate) fn read_texture<T: bytemuck::Pod>(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        texture: &wgpu::Texture,
+        label: &str,
+    ) -> Readback<T> {
+        let extent = texture.size();
+        let texel_size = texture
+            .format()
+            .block_copy_size(None)
+            .expect("readback texture should have a single aspect");
+        let row_size = extent.width * texel_size;
+        let padded_row_size = row_size.div_ceil(COPY_ROW_ALIGNMENT) * COPY_ROW_ALIGNMENT;
+
+        let staging = create_staging(
+            device,
+            padded_row_size as wgpu::BufferAddress * extent.height as wgpu::BufferAddress,
+            label,
+        );
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Readback texture copy - encoder"),
+        });
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &staging,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_row_size),
+                    rows_per_image: Some(extent.height),
+                },
+            },
+            wgpu::Extent3d {
+                depth_or_array_layers: 1,
+                ..extent
+            },
+        );
+        queue.submit(Some(encoder.finish()));
+
+        self.push(staging, label, move |bytes| {
+            if padded_row_size == row_size {
+                return bytes.to_vec();
+            }
+
+            bytes
+                .chunks_exact(padded_row_size as usize)
+                .flat_map(|row| row[..row_size as usize].iter().copied())
+                .collect()
+        })
+    }
+
+    // Runs `callback` on the main thread from `poll` once the data arrives
+    pub(crate) fn on_complete<T, F>(&mut self, readback: Readback<T>, callback: F)
+    where
+        T: 'static,
+        F: FnOnce(ReadbackResult<T>) + 'static,
+    {
+        self.spawn(async move { callback(readback.await) });
+    }
+
+    // Futures are driven from `poll`, they can await any number of readbacks
+    pub(crate) fn spawn<F: Future<Output = ()> + 'static>(&mut self, future: F) {
+        self.pool
+            .spawner()
+            .spawn_local(future)
+            .expect("readback executor should accept tasks");
+    }
+
+    // Call once per frame, never blocks
+    pub(crate) fn poll(&mut self, device: &wgpu::Device) {
+        device.poll(wgpu::Maintain::Poll);
+
+        let mut i = 0;
+        while i < self.pending.len() {
+            let mapped = match self.pending[i].mapped.try_recv() {
+                Ok(Some(result)) => result.map_err(ReadbackError::Map),
+                Ok(None) => {
+                    i += 1;
+                    continue;
+                }
+                Err(_) => Err(ReadbackError::Canceled),
+            };
+
+            let pending = self.pending.swap_remove(i);
+            log::debug!("Readback complete: {}", pending.label);
+
+            match mapped {
+                Ok(()) => {
+                    {
+                        let view = pending.staging.slice(..).get_mapped_range();
+                        (pending.deliver)(Ok(&view));
+                    }
+                    pending.staging.unmap();
+                }
+                Err(e) => (pending.deliver)(Err(e)),
+            }
+        }
+
+        self.pool.run_until_stalled();
+    }
+
+    // Blocks until `readback` resolves, only for paths that must wait anyway
+    pub(crate) fn wait<T>(
+        &mut self,
+        device: &wgpu::Device,
+        mut readback: Readback<T>,
+    ) -> ReadbackResult<T> {
+        loop {
+            if let Some(result) = readback.try_take() {
+                return result;
+            }
+
+            device.poll(wgpu::Maintain::Wait);
+            self.poll(device);
+        }
+    }
+
+    fn push<T, F>(&mut self, staging: wgpu::Buffer, label: &str, unpad: F) -> Readback<T>
+    where
+        T: bytemuck::Pod,
+        F: FnOnce(&[u8]) -> Vec<u8> + 'static,
+    {
+        let (map_tx, map_rx) = oneshot::channel();
+        staging
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let _ = map_tx.send(result);
+            });
+
+        let (tx, rx) = oneshot::channel();
+        let deliver: Deliver = Box::new(move |result: Result<&[u8], ReadbackError>| {
+            // Copied into a fresh Vec<T>, a Vec<u8> has no alignment guarantee for T
+            let data = result.map(|bytes| bytemuck::pod_collect_to_vec::<u8, T>(&unpad(bytes)));
+            let _ = tx.send(data);
+        });
+
+        self.pending.push(PendingReadback {
+            label: label.to_owned(),
+            staging,
+            mapped: map_rx,
+            deliver,
+        });
+
+        Readback { rx }
+    }
+}
+
+fn create_staging(device: &wgpu::Device, size: wgpu::BufferAddress, label: &str) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some(&format!("Readback staging - {label}")),
+        size,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    })
+}
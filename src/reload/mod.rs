@@ -0,0 +1 @@
+pub(crate) mod shader_reload;
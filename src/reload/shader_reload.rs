@@ -0,0 +1,265 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime};
+
+use crate::collections::consts::{SHADER_DIR, SHADER_POLL_INTERVAL};
+use crate::init::init_functions::{
+    create_render_pipeline, create_terrain_pipelines, create_wave_pipelines,
+};
+use crate::init::layout_check::check_shader_layouts;
+use crate::init::shader_compose::{compose_shader, ComposedShader, ShaderFile};
+use crate::post::post_process::PostPipelines;
+use crate::state::app_state::State;
+
+// New pipelines are only swapped in once wgpu has accepted all of them
+enum Rebuilt {
+    Render(wgpu::RenderPipeline),
+    Terrain(wgpu::ComputePipeline, wgpu::ComputePipeline),
+    Waves(
+        wgpu::ComputePipeline,
+        wgpu::ComputePipeline,
+        wgpu::ComputePipeline,
+    ),
+    Post(PostPipelines),
+}
+
+// Polls the modification times under SHADER_DIR, no watcher thread needed
+#[derive(Debug)]
+pub(crate) struct ShaderWatcher {
+    modified: HashMap<PathBuf, SystemTime>,
+    last_poll: Instant,
+}
+
+impl ShaderWatcher {
+    pub(crate) fn new() -> Self {
+        let mut modified = HashMap::new();
+        collect_modified(Path::new(SHADER_DIR), &mut modified);
+
+        Self {
+            modified,
+            last_poll: Instant::now(),
+        }
+    }
+
+    // Paths relative to SHADER_DIR, as written in #include lines
+    pub(crate) fn changed_files(&mut self) -> Vec<String> {
+        if self.last_poll.elapsed().as_secs_f32() < SHADER_POLL_INTERVAL {
+            return Vec::new();
+        }
+        self.last_poll = Instant::now();
+
+        let mut current = HashMap::new();
+        collect_modified(Path::new(SHADER_DIR), &mut current);
+
+        let mut changed: Vec<String> = current
+            .iter()
+            .filter(|(path, time)| self.modified.get(*path) != Some(*time))
+            .filter_map(|(path, _)| shader_name(path))
+            .collect();
+        changed.sort();
+
+        self.modified = current;
+        changed
+    }
+}
+
+fn shader_name(path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(SHADER_DIR).ok()?;
+    let parts: Vec<&str> = relative
+        .components()
+        .filter_map(|c| c.as_os_str().to_str())
+        .collect();
+
+    Some(parts.join("/"))
+}
+
+fn load_from_disk(name: &str) -> Result<String, String> {
+    let path = Path::new(SHADER_DIR).join(name);
+    std::fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+// Recurses into subdirectories, a missing directory just watches nothing
+fn collect_modified(dir: &Path, modified: &mut HashMap<PathBuf, SystemTime>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_modified(&path, modified);
+        } else if path.extension().is_some_and(|ext| ext == "wgsl") {
+            if let Ok(time) = entry.metadata().and_then(|m| m.modified()) {
+                modified.insert(path, time);
+            }
+        }
+    }
+}
+
+// Runs naga over the composed source, errors are reported as file:line:column of the
+// file the line was written in, followed by naga's annotated report
+pub(crate) fn validate_wgsl(
+    file: ShaderFile,
+    composed: &ComposedShader,
+) -> Result<naga::Module, String> {
+    let source = &composed.source;
+    // naga's report quotes the composed source, so its line numbers are marked as such
+    let composed_name = format!("{} (composed)", file.name());
+
+    let module = naga::front::wgsl::parse_str(source).map_err(|e| {
+        format_diagnostic(
+            file,
+            composed,
+            e.location(source),
+            e.message(),
+            &e.emit_to_string_with_path(source, &composed_name),
+        )
+    })?;
+
+    let mut validator = naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::default(),
+    );
+    validator.validate(&module).map_err(|e| {
+        format_diagnostic(
+            file,
+            composed,
+            e.location(source),
+            &e.as_inner().to_string(),
+            &e.emit_to_string_with_path(source, &composed_name),
+        )
+    })?;
+
+    Ok(module)
+}
+
+fn format_diagnostic(
+    file: ShaderFile,
+    composed: &ComposedShader,
+    location: Option<naga::SourceLocation>,
+    message: &str,
+    report: &str,
+) -> String {
+    match location {
+        Some(loc) => {
+            let (origin, line) = composed.origin(loc.line_number);
+            format!(
+                "{}:{}:{}: {}\n{}",
+                origin, line, loc.line_position, message, report
+            )
+        }
+        None => format!("{}: {}\n{}", file.name(), message, report),
+    }
+}
+
+pub(crate) fn reload_changed_shaders(state: &mut State) {
+    let changed = state.shader_watcher.changed_files();
+    if changed.is_empty() {
+        return;
+    }
+
+    // A change to an included file rebuilds every shader that includes it
+    let mut regenerate = false;
+    for file in ShaderFile::ALL {
+        let result = compose_shader(file, &load_from_disk).and_then(|composed| {
+            if !composed.files.iter().any(|f| changed.contains(f)) {
+                return Ok(false);
+            }
+            reload_shader(state, file, composed).map(|()| true)
+        });
+
+        match result {
+            Ok(false) => {}
+            Ok(true) => {
+                println!("Reloaded {}", file.name());
+                // The textures were written by the old compute shaders
+                regenerate |= matches!(
+                    file,
+                    ShaderFile::GenerateTerrain | ShaderFile::WaveDirections
+                );
+            }
+            // The last good pipelines stay in use
+            Err(report) => eprintln!("Shader reload failed:\n{}", report),
+        }
+    }
+
+    // Once both compute shaders are rebuilt, so a shared include regenerates once
+    if regenerate {
+        state.regenerate_terrain();
+    }
+}
+
+fn reload_shader(
+    state: &mut State,
+    file: ShaderFile,
+    composed: ComposedShader,
+) -> Result<(), String> {
+    let parsed = validate_wgsl(file, &composed)?;
+    check_shader_layouts(file.name(), &parsed)?;
+
+    // naga accepting the file doesn't mean it still matches the bind group layouts
+    state.device.push_error_scope(wgpu::ErrorFilter::Validation);
+
+    let module = state
+        .device
+        .create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(file.label()),
+            source: wgpu::ShaderSource::Wgsl(composed.source.into()),
+        });
+
+    let device = &state.device;
+    let bind_groups = &state.bind_groups;
+    let modules = &state.shader_modules;
+    let rebuilt = match file {
+        ShaderFile::Vertex => Rebuilt::Render(create_render_pipeline(
+            device,
+            bind_groups,
+            &module,
+            &modules.f_shader,
+        )),
+        ShaderFile::Fragment => Rebuilt::Render(create_render_pipeline(
+            device,
+            bind_groups,
+            &modules.v_shader,
+            &module,
+        )),
+        ShaderFile::GenerateTerrain => {
+            let (planet, moon) = create_terrain_pipelines(device, bind_groups, &module);
+            Rebuilt::Terrain(planet, moon)
+        }
+        ShaderFile::WaveDirections => {
+            let (seeds, flood, write) = create_wave_pipelines(device, bind_groups, &module);
+            Rebuilt::Waves(seeds, flood, write)
+        }
+        ShaderFile::Post => Rebuilt::Post(state.post.create_pipelines(device, &module)),
+    };
+
+    if let Some(error) = futures::executor::block_on(state.device.pop_error_scope()) {
+        return Err(format!("{}: {}", file.name(), error));
+    }
+
+    match rebuilt {
+        Rebuilt::Render(render) => state.pipelines.render = render,
+        Rebuilt::Terrain(planet, moon) => {
+            state.pipelines.generate_planet_terrain = planet;
+            state.pipelines.generate_moon_terrain = moon;
+        }
+        Rebuilt::Waves(seeds, flood, write) => {
+            state.pipelines.init_coast_seeds = seeds;
+            state.pipelines.jump_flood = flood;
+            state.pipelines.write_wave_directions = write;
+        }
+        Rebuilt::Post(pipelines) => state.post.set_pipelines(pipelines),
+    }
+
+    let modules = &mut state.shader_modules;
+    match file {
+        ShaderFile::Vertex => modules.v_shader = module,
+        ShaderFile::Fragment => modules.f_shader = module,
+        ShaderFile::GenerateTerrain => modules.generate_terrain = module,
+        ShaderFile::WaveDirections => modules.wave_directions = module,
+        ShaderFile::Post => modules.post = module,
+    }
+
+    Ok(())
+}
@@ -0,0 +1,13 @@
+const PI: f32 = 3.14159265358979323846;
+const MAX_F32: f32 = 0x1.fffffep+127f;
+
+// Rotates fbm octaves so their grids don't line up
+const m2: mat2x2<f32> = mat2x2(
+  0.80, 0.60,
+  -0.60, 0.80,
+);
+
+const m2Inv: mat2x2<f32> = mat2x2(
+  0.80, -0.60,
+  0.60, 0.80,
+);
@@ -0,0 +1,5 @@
+struct TimeUniform {
+  time: f32,
+}
+
+@group(0) @binding(0) var<uniform> tu: TimeUniform;
@@ -1,32 +1,14 @@
-const PI: f32 = 3.14159265358979323846;
-const SCREEN_WIDTH: f32 = 1376.0;
-const SCREEN_HEIGHT: f32 = 768.0;
-const I_SCREEN_WIDTH: i32 = 1376;
-const I_SCREEN_HEIGHT: i32 = 768;
-// Incorrect (2048x2048), but when corrected
-// produces less interesting terrain
-const PLANET_TEX_WIDTH: f32 = 2048.0;
-const PLANET_TEX_HEIGHT: f32 = 2048.0;
-
-const MOON_TEX_WIDTH: f32 = 1024.0;
-const MOON_TEX_HEIGHT: f32 = 1024.0;
+#include "common/math.wgsl"
+#include "common/time.wgsl"
+
+// PLANET_TEX_* and MOON_TEX_* are injected from consts.rs
 const NUM_CRATERS: u32 = 4;
 const CRATER_DEPTH: f32 = 0.5;
 const MIN_CRATER_RAD: f32 = 0.15;
 
 const MIN_POSITIVE_F32: f32 = 0x1.0p-126f;
 
-const m2: mat2x2<f32> = mat2x2(
-  0.80, 0.60,
-  -0.60, 0.80,
-);
-
-const m2Inv: mat2x2<f32> = mat2x2(
-  0.80, -0.60,
-  0.60, 0.80,
-);
-
-@group(0) @binding(0) var<uniform> tu: TimeUniform;
+@group(1) @binding(0) var<storage, read_write> tp: TerrainParams;
 
 @group(1) @binding(7) var<storage, read_write> debug_arr1: array<vec4<f32>>;
 @group(1) @binding(8) var<storage, read_write> debug_arr2: array<vec4<f32>>;
@@ -35,8 +17,14 @@ const m2Inv: mat2x2<f32> = mat2x2(
 @group(2) @binding(0) var planet_terrain: texture_storage_2d<rgba32float, read_write>;
 @group(2) @binding(1) var moon_terrain: texture_storage_2d<rgba32float, read_write>;
 
-struct TimeUniform {
-  time: f32,
+struct TerrainParams {
+  octaves: i32,
+  seed: u32,
+}
+
+// Moves the noise domain for each seed, seed 0 keeps the original planet
+fn seed_offset() -> vec2<f32> {
+  return vec2(f32(tp.seed & 0xffffu), f32(tp.seed >> 16u)) * 1.61803;
 }
 
 // FBM
@@ -108,19 +96,20 @@ fn fbm(pos: vec2<f32>, octaves: i32, fraction: f32) -> f32 {
 @workgroup_size(32, 32, 1) 
 fn generate_planet_terrain_map(@builtin(global_invocation_id) id: vec3<u32>) {
   let tx_coord: vec2<u32> = id.xy;
-  let ptx_uv: vec2<f32> = ((2.0 * vec2(f32(tx_coord.x), f32(tx_coord.y))) / vec2(PLANET_TEX_WIDTH,
-  PLANET_TEX_HEIGHT)) - 1.0;
+  let ptx_uv: vec2<f32> = ((2.0 * vec2(f32(tx_coord.x), f32(tx_coord.y))) / vec2(f32(PLANET_TEX_WIDTH),
+  f32(PLANET_TEX_HEIGHT))) - 1.0;
 
   var ptx = textureLoad(planet_terrain, tx_coord);
+  let p = ptx_uv + seed_offset();
 
-  var t1 = fbm(ptx_uv, 11, 0.51)*1.032417;
-  t1 += fbm(ptx_uv, 5, 0.47)*0.9432;
-  t1 += fbm(ptx_uv, 3, 0.53)*-0.541793;
-  t1 += fbm(ptx_uv, 2, 0.53)*-0.441793;
-  t1 += fbm(ptx_uv, 9, 0.49)*0.175379;
+  var t1 = fbm(p, 11, 0.51)*1.032417;
+  t1 += fbm(p, 5, 0.47)*0.9432;
+  t1 += fbm(p, 3, 0.53)*-0.541793;
+  t1 += fbm(p, 2, 0.53)*-0.441793;
+  t1 += fbm(p, 9, 0.49)*0.175379;
   
-  //var sand_mask = smoothstep(0.0, 0.5, fbm(ptx_uv, 2, 0.5)*3.0);
-  let ice_tex = fbm(ptx_uv, 11, 0.125);
+  //var sand_mask = smoothstep(0.0, 0.5, fbm(p, 2, 0.5)*3.0);
+  let ice_tex = fbm(p, 11, 0.125);
 
   ptx += vec4(t1, 0.0, 0.0, ice_tex);
 
@@ -192,14 +181,17 @@ fn generate_craters(tx_uv: vec2<f32>, height: f32) -> Crater {
 @workgroup_size(32, 32, 1) 
 fn generate_moon_terrain_map(@builtin(global_invocation_id) id: vec3<u32>) {
   let tx_coord: vec2<u32> = id.xy;
-  let mtx_uv: vec2<f32> = ((2.0 * vec2(f32(tx_coord.x), f32(tx_coord.y))) / vec2(MOON_TEX_WIDTH,
-  MOON_TEX_HEIGHT)) - 1.0;
+  let mtx_uv: vec2<f32> = ((2.0 * vec2(f32(tx_coord.x), f32(tx_coord.y))) / vec2(f32(MOON_TEX_WIDTH),
+  f32(MOON_TEX_HEIGHT))) - 1.0;
 
   var mtx = textureLoad(moon_terrain, tx_coord);
-  var t1 = fbm(mtx_uv, 5, 0.51)*0.41793;
-  t1 += fbm(mtx_uv, 3, 0.49)*-0.31231;
-  let t2 = fbm(mtx_uv, 7, 0.47)*0.19373;
+  let p = mtx_uv + seed_offset();
+  var t1 = fbm(p, 5, 0.51)*0.41793;
+  t1 += fbm(p, 3, 0.49)*-0.31231;
+  let t2 = fbm(p, 7, 0.47)*0.19373;
 
+  // Crater placement follows the seed too
+  seed = 1234u + tp.seed;
   let craters = generate_craters(mtx_uv, mtx.z);
   mtx.x += t1;
   mtx.y += t2;
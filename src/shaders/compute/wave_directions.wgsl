@@ -0,0 +1,134 @@
+// PLANET_TEX_*, PLANET_RADIUS, WATER_LEVEL and TERRAIN_AMP are injected from consts.rs,
+// planet surface radius is PLANET_RADIUS - tx.x*TERRAIN_AMP
+
+const NO_SEED: u32 = 0xffffffffu;
+const MAX_U32: u32 = 0xffffffffu;
+
+struct JumpFloodStep {
+  step: u32,
+}
+
+@group(2) @binding(0) var planet_terrain: texture_storage_2d<rgba32float, read_write>;
+
+// Nearest coast texel for every texel, packed as y*width + x
+@group(3) @binding(0) var<storage, read> seeds_in: array<u32>;
+@group(3) @binding(1) var<storage, read_write> seeds_out: array<u32>;
+@group(3) @binding(2) var<uniform> jfa: JumpFloodStep;
+
+fn in_bounds(p: vec2<u32>) -> bool {
+  return p.x < PLANET_TEX_WIDTH && p.y < PLANET_TEX_HEIGHT;
+}
+
+fn in_bounds_i(p: vec2<i32>) -> bool {
+  return all(p >= vec2(0)) && in_bounds(vec2<u32>(p));
+}
+
+fn tex_index(p: vec2<u32>) -> u32 {
+  return p.y * PLANET_TEX_WIDTH + p.x;
+}
+
+fn tex_coord(idx: u32) -> vec2<u32> {
+  return vec2(idx % PLANET_TEX_WIDTH, idx / PLANET_TEX_WIDTH);
+}
+
+// The terrain texture doesn't tile, the planet sampler clamps to its edges
+fn clamp_coord(p: vec2<i32>) -> vec2<u32> {
+  let size = vec2(i32(PLANET_TEX_WIDTH), i32(PLANET_TEX_HEIGHT));
+  return vec2<u32>(clamp(p, vec2(0), size - 1));
+}
+
+fn texel_dist2(a: vec2<u32>, b: vec2<u32>) -> u32 {
+  let d = vec2<i32>(b) - vec2<i32>(a);
+  return u32(d.x * d.x + d.y * d.y);
+}
+
+fn is_land(p: vec2<u32>) -> bool {
+  let elevation = textureLoad(planet_terrain, p).x;
+  return PLANET_RADIUS - elevation * TERRAIN_AMP >= WATER_LEVEL;
+}
+
+// Coast texels are land texels touching water, they seed the flood
+@compute
+@workgroup_size(16, 16, 1)
+fn init_coast_seeds(@builtin(global_invocation_id) id: vec3<u32>) {
+  if (!in_bounds(id.xy)) {
+    return;
+  }
+
+  var seed = NO_SEED;
+
+  if (is_land(id.xy)) {
+    let p = vec2<i32>(id.xy);
+    let coast = !is_land(clamp_coord(p + vec2(1, 0)))
+      || !is_land(clamp_coord(p + vec2(-1, 0)))
+      || !is_land(clamp_coord(p + vec2(0, 1)))
+      || !is_land(clamp_coord(p + vec2(0, -1)));
+
+    if (coast) {
+      seed = tex_index(id.xy);
+    }
+  }
+
+  seeds_out[tex_index(id.xy)] = seed;
+}
+
+@compute
+@workgroup_size(16, 16, 1)
+fn jump_flood(@builtin(global_invocation_id) id: vec3<u32>) {
+  if (!in_bounds(id.xy)) {
+    return;
+  }
+
+  let p = vec2<i32>(id.xy);
+  let step = i32(jfa.step);
+  var best = NO_SEED;
+  var best_dist = MAX_U32;
+
+  for (var y: i32 = -1; y <= 1; y++) {
+    for (var x: i32 = -1; x <= 1; x++) {
+      // Offsets past an edge have nothing to offer, seeds never cross it
+      let q = p + vec2(x, y) * step;
+      if (!in_bounds_i(q)) {
+        continue;
+      }
+
+      let seed = seeds_in[tex_index(vec2<u32>(q))];
+
+      if (seed != NO_SEED) {
+        let dist = texel_dist2(id.xy, tex_coord(seed));
+        if (dist < best_dist) {
+          best = seed;
+          best_dist = dist;
+        }
+      }
+    }
+  }
+
+  seeds_out[tex_index(id.xy)] = best;
+}
+
+// Waves on open water run towards the nearest shoreline, which puts them
+// perpendicular to it. Land texels get no direction.
+@compute
+@workgroup_size(16, 16, 1)
+fn write_wave_directions(@builtin(global_invocation_id) id: vec3<u32>) {
+  if (!in_bounds(id.xy)) {
+    return;
+  }
+
+  let seed = seeds_in[tex_index(id.xy)];
+  var wdir = vec2(0.0);
+
+  if (seed != NO_SEED && !is_land(id.xy)) {
+    let dir = vec2<f32>(vec2<i32>(tex_coord(seed)) - vec2<i32>(id.xy));
+    if (length(dir) > 0.0) {
+      wdir = normalize(dir);
+    }
+  }
+
+  var ptx = textureLoad(planet_terrain, id.xy);
+  ptx.y = wdir.x;
+  ptx.z = wdir.y;
+
+  textureStore(planet_terrain, id.xy, ptx);
+}
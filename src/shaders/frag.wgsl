@@ -1,28 +1,16 @@
-const PI: f32 = 3.14159265358979323846;
-const MAX_F32: f32 = 0x1.fffffep+127f;
+#include "common/math.wgsl"
+#include "common/time.wgsl"
 
-const SCREEN_WIDTH: f32 = 1376.0;
-const SCREEN_HEIGHT: f32 = 768.0;
+// SCREEN_*, PLANET_*, MOON_*, WATER_LEVEL, ICE_LEVEL, AA_* and DEBUG_VIEW_*
+// are injected from consts.rs
 const ASPECT: f32 = SCREEN_WIDTH / SCREEN_HEIGHT;
 const INV_ASPECT: f32 = SCREEN_HEIGHT / SCREEN_WIDTH;
-// Used extremely low FOV to reduce edge distortion of moon sdf
-// Combined with placing the camera extremely far from the objects
-const FOV: f32 = 0.349066; // 20 degrees
 
 const CENTER: vec3<f32> = vec3(0.0);
-const PLANET_ROTATION: f32 = 0.1;
-const PLANET_RADIUS: f32 = 50.0;
-
-const MOON_RADIUS: f32 = 8.0;
-const MOON_ORBIT_SPEED: f32 = 0.2;
-const MOON_ORBIT_RADIUS: f32 = 75.0;
-const MOON_ORBIT_INCLINATION: f32 = 5.0;
 
 // Measured via distance from planet center
-const WATER_LEVEL: f32 = 50.3;
 const SAND_LEVEL: f32 = WATER_LEVEL + 0.2 ;
 const PLANT_LEVEL: f32 = WATER_LEVEL + 2.3;
-const ICE_LEVEL: f32 = WATER_LEVEL + 3.3;
 const PLANET_LIMIT: f32 = WATER_LEVEL + 10.0;
 
 // Steepness thresholds
@@ -39,54 +27,236 @@ const EARTH_CLR: vec3<f32> = vec3(0.3, 0.18, 0.1);
 const ROCK_CLR: vec3<f32> = vec3(0.2, 0.2, 0.2);
 const ICE_CLR: vec3<f32> = vec3(1.0, 1.0, 1.0);
 
-const PLANT_REFLECTIVITY: f32 = 0.25;
-const ROCK_REFLECTIVITY: f32 = 0.35;
-const SAND_REFLECTIVITY: f32 = 0.5;
-const WATER_REFLECTIVITY: f32 = 0.9;
-const ICE_REFLECTIVITY: f32 = 1.0;
-const MOON_REFLECTIVITY: f32 = 0.6;
-
-const m2: mat2x2<f32> = mat2x2(
-  0.80, 0.60,
-  -0.60, 0.80,
-);
-
-const m2Inv: mat2x2<f32> = mat2x2(
-  0.80, -0.60,
-  0.60, 0.80,
-);
-
-struct TimeUniform {
-    time: f32,
-};
+// Indices into the material table uploaded from materials.rs
+const MATERIAL_COUNT: u32 = 6u;
+const MATERIAL_WATER: u32 = 1u;
+// Sun irradiance, PI makes a white Lambert surface facing the sun exactly 1.0
+const SUN_IRRADIANCE: f32 = PI;
+const AMBIENT_LIGHT: f32 = 0.05;
+
+const ATMOSPHERE_VIEW_SAMPLES: i32 = 16;
+const ATMOSPHERE_LIGHT_SAMPLES: i32 = 6;
+// Mie extinction is scattering plus absorption
+const MIE_EXTINCTION: f32 = 1.1;
+
+const WATER_IOR: f32 = 1.33;
+// Per unit of depth, red is absorbed first
+const WATER_ABSORPTION: vec3<f32> = vec3(0.45, 0.12, 0.07);
+const WATER_SCATTER_CLR: vec3<f32> = vec3(0.0, 0.05, 0.09);
+// Sandy shallows, also used flat where a refracted ray never reaches the seabed
+const SEABED_CLR: vec3<f32> = vec3(0.45, 0.4, 0.25);
+// Depth over which the seabed turns from sand to rock
+const SEABED_ROCK_DEPTH: vec2<f32> = vec2(0.3, 2.0);
+const SEABED_MAX_STEPS: i32 = 32;
+// Further than this through the water the seabed is all but absorbed anyway
+const SEABED_MAX_DIST: f32 = 12.0;
+const FOAM_CLR: vec3<f32> = vec3(0.9, 0.95, 1.0);
+const SUN_GLINT_INTENSITY: f32 = 8.0;
+
+// Bounding spheres for the ray march, padded past the tallest relief and waves
+const PLANET_BOUND: f32 = PLANET_LIMIT + 1.0;
+const MOON_BOUND: f32 = MOON_RADIUS + 3.0;
+// Outside a bound by more than this, the distance to the bound is used as the step
+const BOUND_MARGIN: f32 = 0.1;
+// Hits only need to be accurate to a fraction of the pixel they land in
+const EPSILON_PIXEL_FRACTION: f32 = 0.25;
+const BISECTION_STEPS: i32 = 8;
+
+// Full range of the slope and water depth ramps
+const DEBUG_MAX_SLOPE: f32 = 60.0;
+const DEBUG_MAX_WATER_DEPTH: f32 = 5.0;
+// Overshoot view, fully red at this depth past the surface
+const OVERSHOOT_DEBUG_DEPTH: f32 = 0.5;
+const OVERSHOOT_DEBUG_CLR: vec3<f32> = vec3(1.0, 0.0, 0.2);
+// Material view colours
+const DEBUG_WATER_CLR: vec3<f32> = vec3(0.1, 0.3, 1.0);
+const DEBUG_MOON_CLR: vec3<f32> = vec3(0.6, 0.3, 0.8);
+
+const SHADOW_STEPS: i32 = 48;
+const SHADOW_MAX_STEP: f32 = 2.0;
+// Beyond the tallest relief, distant occluders are covered by get_eclipse
+const SHADOW_MAX_DIST: f32 = 40.0;
+
+const CLOUD_VIEW_SAMPLES: i32 = 32;
+const CLOUD_LIGHT_SAMPLES: i32 = 4;
+const CLOUD_ALBEDO: f32 = 0.9;
+const CLOUD_AMBIENT: vec3<f32> = vec3(0.04, 0.05, 0.07);
+
+const RING_BAND_OCTAVES: i32 = 4;
+const RING_ALBEDO: f32 = 0.8;
+const RING_AMBIENT: vec3<f32> = vec3(0.02, 0.02, 0.025);
+// Fraction of the ring width over which the inner and outer edges fade out
+const RING_EDGE_FADE: f32 = 0.03;
+
+// Faintest star magnitude, each step of 1.0 is 2.512x dimmer
+const STAR_MAX_MAGNITUDE: f32 = 6.0;
+// Star radius as a fraction of its cell
+const STAR_RADIUS: f32 = 0.08;
+const STAR_CLR_COOL: vec3<f32> = vec3(1.0, 0.62, 0.38);
+const STAR_CLR_HOT: vec3<f32> = vec3(0.62, 0.74, 1.0);
+// Normal of the Milky Way band, tilted away from the planet's axis
+const GALACTIC_NORTH: vec3<f32> = vec3(0.34, 0.87, 0.36);
+const NEBULA_WIDTH: f32 = 0.18;
+const NEBULA_CLR1: vec3<f32> = vec3(0.55, 0.45, 0.7);
+const NEBULA_CLR2: vec3<f32> = vec3(0.9, 0.7, 0.5);
 
 struct RayParams {
   epsilon: f32,
   max_dist: f32,
   max_steps: f32,
+  accelerate: f32,
+  relaxation: f32,
+  gradient_bound: f32,
 }
-struct ViewParams {
-  x_shift: f32,
-  y_shift: f32,
-  zoom: f32,
-  x_rot: f32,
-  y_rot: f32,
-  time_modifier: f32,
+
+struct DebugParams {
+  view: f32,
+  // 0 -> 3, red to alpha
+  heightmap_channel: f32,
+  // Step count shown as the top of the heatmap
+  step_range: f32,
+}
+// Camera pose from the orbit or free-fly controller, identity looks down +z
+struct CameraParams {
+  position_x: f32,
+  position_y: f32,
+  position_z: f32,
+  fov_y: f32,
+  rotation_x: f32,
+  rotation_y: f32,
+  rotation_z: f32,
+  rotation_w: f32,
+  near: f32,
+  far: f32,
 }
 
-// GROUPS AND BINDINGS
-@group(0) @binding(0) var<uniform> tu: TimeUniform;
+struct WaterParams {
+  wave_amplitude: f32,
+  wave_speed: f32,
+  wave_frequency: f32,
+  shoaling: f32,
+  foam_width: f32,
+}
+
+struct AtmosphereParams {
+  outer_radius: f32,
+  density: f32,
+  rayleigh_scale_height: f32,
+  mie_scale_height: f32,
+  rayleigh_r: f32,
+  rayleigh_g: f32,
+  rayleigh_b: f32,
+  mie_coefficient: f32,
+  mie_g: f32,
+  sun_intensity: f32,
+}
+
+struct Material {
+  albedo: vec3<f32>,
+  roughness: f32,
+  metallic: f32,
+  reflectance: f32,
+  _padding: vec2<f32>,
+}
+
+struct LightParams {
+  sun_azimuth: f32,
+  sun_elevation: f32,
+  sun_distance: f32,
+  orbit_speed: f32,
+  sun_r: f32,
+  sun_g: f32,
+  sun_b: f32,
+  sun_intensity: f32,
+  angular_radius: f32,
+  fill_enabled: f32,
+  fill_azimuth: f32,
+  fill_elevation: f32,
+  fill_r: f32,
+  fill_g: f32,
+  fill_b: f32,
+  fill_intensity: f32,
+}
 
+struct CloudParams {
+  enabled: f32,
+  coverage: f32,
+  altitude: f32,
+  thickness: f32,
+  wind_speed: f32,
+  density: f32,
+  noise_scale: f32,
+}
+
+struct BackgroundParams {
+  star_scale: f32,
+  star_density: f32,
+  star_brightness: f32,
+  nebula_enabled: f32,
+  nebula_intensity: f32,
+  sun_disc_intensity: f32,
+  env_map_enabled: f32,
+  env_intensity: f32,
+  env_rotation: f32,
+}
+
+struct RingParams {
+  enabled: f32,
+  inner_radius: f32,
+  outer_radius: f32,
+  tilt: f32,
+  opacity: f32,
+  band_frequency: f32,
+  scatter_g: f32,
+  inner_r: f32,
+  inner_g: f32,
+  inner_b: f32,
+  outer_r: f32,
+  outer_g: f32,
+  outer_b: f32,
+}
+
+struct AntiAliasParams {
+  mode: f32,
+  ssaa_samples: f32,
+  taa_blend: f32,
+  history_valid: f32,
+  jitter_x: f32,
+  jitter_y: f32,
+  frame_index: f32,
+  prev_time: f32,
+  prev_position_x: f32,
+  prev_position_y: f32,
+  prev_position_z: f32,
+  prev_fov_y: f32,
+  prev_rotation_x: f32,
+  prev_rotation_y: f32,
+  prev_rotation_z: f32,
+  prev_rotation_w: f32,
+}
+
+// GROUPS AND BINDINGS
 @group(1) @binding(0) var<storage, read_write> rp: RayParams;
-@group(1) @binding(1) var<storage, read_write> vp: ViewParams;
+@group(1) @binding(1) var<storage, read> cam: CameraParams;
+@group(1) @binding(2) var<storage, read_write> wp: WaterParams;
+@group(1) @binding(3) var<storage, read_write> ap: AtmosphereParams;
+@group(1) @binding(4) var<storage, read_write> cp: CloudParams;
+@group(1) @binding(5) var<storage, read> materials: array<Material>;
+@group(1) @binding(6) var<storage, read_write> lp: LightParams;
 @group(1) @binding(7) var<storage, read_write> debug_arr1: array<vec4<f32>>;
 @group(1) @binding(8) var<storage, read_write> debug_arr2: array<vec4<f32>>;
 @group(1) @binding(9) var<storage, read_write> debug: vec4<f32>;
+@group(1) @binding(10) var<storage, read_write> bp: BackgroundParams;
+@group(1) @binding(11) var<storage, read_write> aa: AntiAliasParams;
+@group(1) @binding(12) var<storage, read> dbg: DebugParams;
+@group(1) @binding(13) var<storage, read> rings: RingParams;
 
 @group(2) @binding(0) var planet_tex: texture_2d<f32>;
 @group(2) @binding(1) var planet_sampler: sampler;
 @group(2) @binding(2) var moon_tex: texture_2d<f32>;
 @group(2) @binding(3) var moon_sampler: sampler;
+@group(2) @binding(4) var env_tex: texture_2d<f32>;
+@group(2) @binding(5) var env_sampler: sampler;
 
 // ASPECT RATIO
 fn scale_aspect(fc: vec2<f32>) -> vec2<f32> {
@@ -96,7 +266,40 @@ fn scale_aspect(fc: vec2<f32>) -> vec2<f32> {
   return uv;
 }
 
+fn unscale_aspect(uv: vec2<f32>) -> vec2<f32> {
+  let ndc = vec2(uv.x, -uv.y / INV_ASPECT);
+  return (ndc + 1.0) * vec2(SCREEN_WIDTH, SCREEN_HEIGHT) * 0.5;
+}
+
+
 // LIGHTING
+// Unit vector from the planet center, azimuth 0.0 faces the default camera
+fn light_direction(azimuth: f32, elevation: f32) -> vec3<f32> {
+  return vec3(
+    cos(elevation) * sin(azimuth),
+    sin(elevation),
+    -cos(elevation) * cos(azimuth),
+  );
+}
+
+// The sun orbits the planet's axis for the day/night cycle
+fn get_light_pos() -> vec3<f32> {
+  let azimuth = lp.sun_azimuth + lp.orbit_speed * tu.time;
+  return light_direction(azimuth, lp.sun_elevation) * lp.sun_distance;
+}
+
+fn get_sun_color() -> vec3<f32> {
+  return vec3(lp.sun_r, lp.sun_g, lp.sun_b) * lp.sun_intensity;
+}
+
+fn get_fill_light_dir() -> vec3<f32> {
+  return light_direction(lp.fill_azimuth, lp.fill_elevation);
+}
+
+fn get_fill_light_color() -> vec3<f32> {
+  return vec3(lp.fill_r, lp.fill_g, lp.fill_b) * lp.fill_intensity * lp.fill_enabled;
+}
+
 fn get_normal(pos: vec3<f32>, uv: vec2<f32>) -> vec3<f32> {
   let e = vec2(rp.epsilon, 0.0);
   let n = vec3(map(pos, uv).dist) - 
@@ -123,22 +326,68 @@ fn get_ambient_occlusion(pos: vec3<f32>, normal: vec3<f32>, uv: vec2<f32>) -> f3
   return 1.0 - clamp(0.6 * occ, 0.0, 1.0);
 }
 
-fn get_soft_shadow(pos: vec3<f32>, light_pos: vec3<f32>, uv: vec2<f32>) -> f32 {
+// Self-shadowing of the body pos sits on, the other body is handled
+// analytically by get_eclipse
+fn get_soft_shadow(pos: vec3<f32>, l: vec3<f32>, uv: vec2<f32>) -> f32 {
+  let on_moon = length(pos) > PLANET_LIMIT;
+  // Penumbra width follows the apparent size of the sun
+  let penumbra = max(lp.angular_radius, 0.001);
   var res = 1.0;
-  var dist = 0.01;
-  let light_size = 100.0;
+  var dist = rp.epsilon * 5.0;
 
-  for (var i: i32 = 0; i < 8; i++) {
-    let hit = map(pos + light_pos * dist, uv).dist;
-    res = min(res, hit / (dist * light_size));
-    if (hit < rp.epsilon) { break; }
-    dist += hit;
-    if (dist > 50.0) { break; }
+  for (var i: i32 = 0; i < SHADOW_STEPS; i++) {
+    let p = pos + l * dist;
+    var hit = 0.0;
+    if (on_moon) {
+      hit = get_moon(p, uv).dist;
+    } else {
+      hit = get_terrain(p, uv).dist;
+    }
+
+    res = min(res, hit / (dist * penumbra));
+    if (res < 0.001) { break; }
+    dist += clamp(hit, rp.epsilon, SHADOW_MAX_STEP);
+    if (dist > SHADOW_MAX_DIST) { break; }
   }
 
-  return clamp(res, 0.0, 1.0);
+  return smoothstep(0.0, 1.0, clamp(res, 0.0, 1.0));
 }
 
+// Visible fraction of the sun disc when a sphere passes in front of it
+fn sphere_occlusion(pos: vec3<f32>, l: vec3<f32>, center: vec3<f32>, radius: f32) -> f32 {
+  let to_center = center - pos;
+  let dist = length(to_center);
+
+  // Only bodies between pos and the sun can eclipse it
+  if (dist <= radius || dot(to_center, l) <= 0.0) {
+    return 1.0;
+  }
+
+  let sun_radius = max(lp.angular_radius, 0.0001);
+  let body_radius = asin(radius / dist);
+  let separation = acos(clamp(dot(l, to_center / dist), -1.0, 1.0));
+
+  // Fully covered in the umbra, an annulus when the body looks smaller than the sun
+  let max_cover = min(1.0, (body_radius * body_radius) / (sun_radius * sun_radius));
+  let overlap = 1.0 - smoothstep(
+    abs(sun_radius - body_radius),
+    sun_radius + body_radius,
+    separation
+  );
+
+  return 1.0 - max_cover * overlap;
+}
+
+// The moon's umbra and penumbra on the planet, and the planet's shadow on the moon
+fn get_eclipse(pos: vec3<f32>, l: vec3<f32>) -> f32 {
+  if (length(pos) > PLANET_LIMIT) {
+    return sphere_occlusion(pos, l, CENTER, PLANET_RADIUS);
+  }
+
+  return sphere_occlusion(pos, l, -get_moon_position(), MOON_RADIUS);
+}
+
+// Blend weights into the material table, in table order
 struct MaterialEnum {
   ice: f32,
   water: f32,
@@ -148,55 +397,136 @@ struct MaterialEnum {
   moon: f32,
 }
 
+struct Surface {
+  albedo: vec3<f32>,
+  roughness: f32,
+  metallic: f32,
+  reflectance: f32,
+}
+
+fn blend_materials(material: MaterialEnum, base_clr: vec3<f32>) -> Surface {
+  var weights = array<f32, MATERIAL_COUNT>(
+    material.ice,
+    material.water,
+    material.rock,
+    material.plant,
+    material.sand,
+    material.moon,
+  );
+  let total = max(
+    material.ice + material.water + material.rock + material.plant + material.sand + material.moon,
+    0.0001
+  );
+
+  var surface = Surface(vec3(0.0), 0.0, 0.0, 0.0);
+  for (var i: u32 = 0u; i < MATERIAL_COUNT; i++) {
+    let w = weights[i] / total;
+    let m = materials[i];
+    surface.albedo += m.albedo * w;
+    surface.roughness += m.roughness * w;
+    surface.metallic += m.metallic * w;
+    surface.reflectance += m.reflectance * w;
+  }
+
+  surface.albedo *= base_clr;
+  return surface;
+}
+
+// GGX normal distribution
+fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+  let a = roughness * roughness;
+  let a2 = a * a;
+  let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+  return a2 / (PI * d * d);
+}
+
+// Smith shadowing-masking with the Schlick-GGX approximation
+fn geometry_smith(n_dot_v: f32, n_dot_l: f32, roughness: f32) -> f32 {
+  let k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+  let gv = n_dot_v / (n_dot_v * (1.0 - k) + k);
+  let gl = n_dot_l / (n_dot_l * (1.0 - k) + k);
+  return gv * gl;
+}
+
+fn fresnel_schlick(cos_theta: f32, f0: vec3<f32>) -> vec3<f32> {
+  return f0 + (1.0 - f0) * pow(1.0 - cos_theta, 5.0);
+}
+
+// Cook-Torrance specular plus Lambert diffuse for a single light, without the light colour
+fn get_brdf(surface: Surface, normal: vec3<f32>, v: vec3<f32>, l: vec3<f32>, occ: f32) -> vec3<f32> {
+  let h: vec3<f32> = normalize(l + v);
+
+  let n_dot_l = max(dot(normal, l), 0.0);
+  let n_dot_v = max(dot(normal, v), 0.0001);
+  let n_dot_h = max(dot(normal, h), 0.0);
+
+  // Metals tint their reflections and have no diffuse term
+  let f0 = mix(vec3(surface.reflectance), surface.albedo, surface.metallic);
+  let fresnel = fresnel_schlick(max(dot(h, v), 0.0), f0);
+  let d = distribution_ggx(n_dot_h, surface.roughness);
+  let g = geometry_smith(n_dot_v, n_dot_l, surface.roughness);
+  let specular = d * g * fresnel / max(4.0 * n_dot_v * n_dot_l, 0.0001);
+  let diffuse = (1.0 - fresnel) * (1.0 - surface.metallic) * surface.albedo / PI;
+
+  return (diffuse + specular * occ) * n_dot_l;
+}
+
 fn get_light(
   pos: vec3<f32>,
   rd: vec3<f32>,
   uv: vec2<f32>,
   material: MaterialEnum,
+  base_clr: vec3<f32>,
 ) -> vec3<f32> {
-  var light_pos: vec3<f32> = vec3(40.0, 50.0, -500.0);
-  let color: vec3<f32> = vec3(1.0);
+  var light_pos: vec3<f32> = get_light_pos();
+  // Sunlight reaching the surface is reddened by the atmosphere near the terminator
+  let color: vec3<f32> = get_sun_transmittance(pos) * get_sun_color() * SUN_IRRADIANCE;
+  let surface = blend_materials(material, base_clr);
 
   let l: vec3<f32> = normalize(light_pos - pos);
   let normal: vec3<f32> = get_normal(pos, uv);
-
   let v: vec3<f32> = -rd;
-  let r: vec3<f32> = reflect(-l, normal);
-
-  let diff: f32 = 0.70 * max(dot(l, normal), 0.0);
-  let specular: f32 = 0.30 * pow(clamp(dot(r, v), 0.0, 1.0), 10.0);
-  let ambient: f32 = 0.05; 
-
-  var reflect: f32 = 0.0;
-  reflect += material.ice*ICE_REFLECTIVITY;
-  reflect += material.water*WATER_REFLECTIVITY;
-  reflect += material.rock*ROCK_REFLECTIVITY;
-  reflect += material.plant*PLANT_REFLECTIVITY;
-  reflect += material.sand*SAND_REFLECTIVITY;
-  reflect += material.moon*MOON_REFLECTIVITY;
-  let spec_ref = specular*reflect;
-  let diff_ref = diff*reflect;
-
-  let shadow: f32 = get_soft_shadow(pos, light_pos, uv);
+
+  let shadow: f32 = get_soft_shadow(pos, l, uv) * get_cloud_shadow(pos, l)
+    * get_ring_shadow(pos, l);
   let occ: f32 = get_ambient_occlusion(pos, normal, uv);
 
-  return (ambient * occ + (spec_ref * occ + diff_ref) * shadow) * color;
+  let ambient = AMBIENT_LIGHT * surface.albedo * occ;
+  let sun = get_brdf(surface, normal, v, l, occ) * color * shadow;
+
+  // The fill light casts no shadows, it only lifts the dark side
+  var fill = vec3(0.0);
+  if (lp.fill_enabled > 0.5) {
+    fill = get_brdf(surface, normal, v, get_fill_light_dir(), occ) * get_fill_light_color()
+      * SUN_IRRADIANCE;
+  }
+
+  return ambient + sun + fill;
 }
 
 // CAMERA
 
-fn get_cam(ro: vec3<f32>, look_at: vec3<f32>) -> mat4x4<f32> {
-  let camf = normalize(vec3(look_at - ro));
-  let camr = normalize(cross(vec3(0.0, 1.0, 0.0), camf));
-  let camu = cross(camf, camr);
-  let camp = vec4(-ro.x, -ro.y, -ro.z, 1.0);
-
-  return mat4x4(
-    vec4(camr, 0.0), 
-    vec4(camu, 0.0), 
-    vec4(camf, 0.0), 
-    camp
-  );
+fn quat_rotate(q: vec4<f32>, v: vec3<f32>) -> vec3<f32> {
+  let t = 2.0 * cross(q.xyz, v);
+  return v + q.w * t + cross(q.xyz, t);
+}
+
+fn quat_conjugate(q: vec4<f32>) -> vec4<f32> {
+  return vec4(-q.xyz, q.w);
+}
+
+fn camera_position() -> vec3<f32> {
+  return vec3(cam.position_x, cam.position_y, cam.position_z);
+}
+
+fn camera_rotation() -> vec4<f32> {
+  return vec4(cam.rotation_x, cam.rotation_y, cam.rotation_z, cam.rotation_w);
+}
+
+// Half the screen height spans tan(fov_y / 2), uv.y runs to INV_ASPECT
+fn camera_ray(uv: vec2<f32>, rotation: vec4<f32>, fov_y: f32) -> vec3<f32> {
+  let local = vec3(uv * tan(0.5 * fov_y) * ASPECT, 1.0);
+  return normalize(quat_rotate(rotation, local));
 }
 
 fn rotate3d(v: vec3<f32>, angleX: f32, angleY: f32) -> vec3<f32> {
@@ -270,7 +600,11 @@ fn sphereSDF(pos: vec3<f32>, radius: f32) -> f32 {
 
 // MOON
 fn get_moon_position() -> vec3<f32> {
-  let angle = tu.time*MOON_ORBIT_SPEED;
+  return get_moon_position_at(tu.time);
+}
+
+fn get_moon_position_at(time: f32) -> vec3<f32> {
+  let angle = time*MOON_ORBIT_SPEED;
   let x = MOON_ORBIT_RADIUS*cos(angle);
   let z = MOON_ORBIT_RADIUS*sin(angle);
   let y = MOON_ORBIT_INCLINATION*sin(angle)*3.0;
@@ -304,10 +638,225 @@ fn get_moon(pos: vec3<f32>, uv: vec2<f32>) -> Moon {
   return Moon(dist, mtx.w);
 }
 
+// OCEAN
+// Peaked crests approximate the Gerstner profile without moving the surface sideways
+fn wave_profile(phase: f32) -> f32 {
+  return exp(2.0 * (sin(phase) - 1.0));
+}
+
+// Three crossing wave trains around the direction stored in the planet texture,
+// coordinates are in planet texture space
+fn wave_train(coord: vec2<f32>, dir: vec2<f32>) -> f32 {
+  if (length(dir) < 0.001) {
+    return 0.0;
+  }
+
+  let d0 = normalize(dir);
+  let d1 = normalize(d0 + 0.4 * vec2(-d0.y, d0.x));
+  let d2 = normalize(d0 - 0.4 * vec2(-d0.y, d0.x));
+
+  let k = 2.0 * PI * wp.wave_frequency;
+  let t = tu.time * wp.wave_speed;
+
+  var h = wave_profile(k * dot(d0, coord) - t * k);
+  h += 0.5 * wave_profile(1.7 * k * dot(d1, coord) - 1.3 * t * k);
+  h += 0.35 * wave_profile(2.3 * k * dot(d2, coord) - 1.5 * t * k);
+
+  return h / 1.85;
+}
+
+// Returns the crest factor in 0.0 -> 1.0, scale by the local amplitude for height
+fn get_waves(pos: vec3<f32>) -> f32 {
+  let l = length(pos);
+
+  // Same projections as tex_triplanar_mapping so the wave directions line up
+  let tex_XY = vec2(pos.x / l, pos.y / l) * 0.5 + 0.47;
+  let tex_XZ = vec2(pos.x / l, pos.z / l) * 0.5 + 0.51;
+  let tex_YZ = vec2(pos.y / l, pos.z / l) * 0.5 + 0.55;
+
+  var n = abs(pos / l);
+  n *= n*n*n*n*n;
+  n /= n.x + n.y + n.z;
+
+  let dir_XY = textureSampleLevel(planet_tex, planet_sampler, tex_XY, 0.0).yz;
+  let dir_XZ = textureSampleLevel(planet_tex, planet_sampler, tex_XZ, 0.0).yz;
+  let dir_YZ = textureSampleLevel(planet_tex, planet_sampler, tex_YZ, 0.0).yz;
+
+  return wave_train(tex_XY, dir_XY) * n.z
+    + wave_train(tex_XZ, dir_XZ) * n.y
+    + wave_train(tex_YZ, dir_YZ) * n.x;
+}
+
+// Waves die out on the shoreline but steepen in the shallows before that
+fn get_wave_amplitude(water_depth: f32) -> f32 {
+  let shallow = 1.0 - smoothstep(0.0, 1.0, water_depth);
+  let shore_fade = smoothstep(0.0, 0.05, water_depth);
+  return wp.wave_amplitude * (1.0 + wp.shoaling * shallow) * shore_fade;
+}
+
+// Sky, moon and sun seen in the water
+fn get_water_reflection(pos: vec3<f32>, rr: vec3<f32>, uv: vec2<f32>) -> vec3<f32> {
+  var clr = vec3(0.0);
+  var max_t = MAX_F32;
+
+  // The moon is centred on -get_moon_position()
+  let moon_hit = ray_sphere(pos + get_moon_position(), rr, MOON_RADIUS);
+  if (moon_hit.x > 0.0 && moon_hit.x < moon_hit.y) {
+    max_t = moon_hit.x;
+    let moon_material = MaterialEnum(0.0, 0.0, 0.0, 0.0, 0.0, 1.0);
+    clr = get_light(pos + rr * max_t, rr, uv, moon_material, ICE_CLR);
+  } else {
+    let l = normalize(get_light_pos() - pos);
+    // pow(cos(a), n) falls to 1/e at the edge of the sun disc
+    let glint_exponent = 2.0 / max(lp.angular_radius * lp.angular_radius, 0.000001);
+    let glint = pow(max(dot(rr, l), 0.0), glint_exponent);
+    clr = get_sun_transmittance(pos) * get_sun_color() * glint * SUN_GLINT_INTENSITY
+      * get_cloud_shadow(pos, l) * get_ring_shadow(pos, l) + get_sky(rr);
+  }
+
+  let sky = get_atmosphere(pos, rr, max_t);
+  return clr * sky.transmittance + sky.inscatter;
+}
+
+// Solid ground under the water, get_terrain without the water shell
+fn get_seabed_dist(pos: vec3<f32>, uv: vec2<f32>) -> f32 {
+  let rPos = rotate3d(pos, 0.0, PLANET_ROTATION*tu.time);
+  let tx = tex_triplanar_mapping(
+    rPos, uv,
+    PLANET_RADIUS, TERRAIN_AMP,
+    planet_tex, planet_sampler
+  );
+
+  return sphereSDF(rPos, PLANET_RADIUS) + tx.x;
+}
+
+fn get_seabed_normal(pos: vec3<f32>, uv: vec2<f32>) -> vec3<f32> {
+  let e = vec2(rp.epsilon, 0.0);
+  let n = vec3(get_seabed_dist(pos, uv)) -
+  vec3(
+    get_seabed_dist(pos - e.xyy, uv),
+    get_seabed_dist(pos - e.yxy, uv),
+    get_seabed_dist(pos - e.yyx, uv)
+  );
+
+  return normalize(n);
+}
+
+// Distance along rt from the water surface to the seabed, negative when the
+// march gives up first. Steps use the same gradient bound as the terrain march.
+fn trace_seabed(pos: vec3<f32>, rt: vec3<f32>, uv: vec2<f32>) -> f32 {
+  let gradient_bound = max(rp.gradient_bound, 1.0);
+  var dist = rp.epsilon * 2.0;
+
+  for (var i: i32 = 0; i < SEABED_MAX_STEPS; i++) {
+    let d = get_seabed_dist(pos + rt * dist, uv);
+    if (d < rp.epsilon) {
+      return dist;
+    }
+
+    dist += d / gradient_bound;
+    if (dist > SEABED_MAX_DIST) {
+      break;
+    }
+  }
+
+  return -1.0;
+}
+
+// Seabed at the end of a refracted ray, sand in the shallows and rock deeper down.
+// Sunlight is absorbed on the way down to it.
+fn get_seabed_light(pos: vec3<f32>, rt: vec3<f32>, uv: vec2<f32>, sun: vec3<f32>) -> vec3<f32> {
+  let up = normalize(pos);
+  let l = normalize(get_light_pos() - pos);
+  let depth = max(PLANET_RADIUS - length(pos), 0.0);
+
+  let rock = smoothstep(SEABED_ROCK_DEPTH.x, SEABED_ROCK_DEPTH.y, depth);
+  let material = MaterialEnum(0.0, 0.0, rock, 0.0, 1.0 - rock, 0.0);
+  let surface = blend_materials(material, mix(SEABED_CLR, ROCK_CLR, rock));
+  let normal = get_seabed_normal(pos, uv);
+
+  let light_path = depth / max(dot(l, up), 0.15);
+  let lit = get_brdf(surface, normal, -rt, l, 1.0) * sun * exp(-WATER_ABSORPTION * light_path);
+
+  return lit + AMBIENT_LIGHT * surface.albedo;
+}
+
+// Seabed seen along the refracted ray, absorbed over the distance the ray
+// actually travels through the water
+fn get_water_refraction(
+  pos: vec3<f32>,
+  rd: vec3<f32>,
+  uv: vec2<f32>,
+  normal: vec3<f32>,
+  water_depth: f32,
+) -> vec3<f32> {
+  let up = normalize(pos);
+  let l = normalize(get_light_pos() - pos);
+  let rt = refract(rd, normal, 1.0 / WATER_IOR);
+
+  // Sunlight entering the water, before the angle it meets a surface at
+  let sun_in = get_sun_transmittance(pos) * get_sun_color() * get_cloud_shadow(pos, l)
+    * get_ring_shadow(pos, l) * SUN_IRRADIANCE;
+  let sun = sun_in * max(dot(up, l), 0.0);
+
+  var hit = -1.0;
+  if (dot(rt, rt) > 0.0) {
+    hit = trace_seabed(pos, rt, uv);
+  }
+
+  var seabed: vec3<f32>;
+  var view_path: f32;
+  if (hit >= 0.0) {
+    view_path = hit;
+    seabed = get_seabed_light(pos + rt * hit, rt, uv, sun_in);
+  } else {
+    // Flat seabed at the depth under the entry point
+    view_path = water_depth / max(dot(-rt, up), 0.15);
+    let light_path = water_depth / max(dot(l, up), 0.15);
+    seabed = SEABED_CLR / PI * sun * exp(-WATER_ABSORPTION * light_path)
+      + AMBIENT_LIGHT * SEABED_CLR;
+  }
+
+  let scattered = WATER_SCATTER_CLR * sun * (1.0 - exp(-WATER_ABSORPTION * view_path));
+
+  return seabed * exp(-WATER_ABSORPTION * view_path) + scattered;
+}
+
+fn get_water(
+  pos: vec3<f32>,
+  rd: vec3<f32>,
+  uv: vec2<f32>,
+  material: MaterialEnum,
+  water_depth: f32,
+  wave_crest: f32,
+) -> vec3<f32> {
+  let normal = get_normal(pos, uv);
+  let f0 = vec3(materials[MATERIAL_WATER].reflectance);
+  let fresnel = fresnel_schlick(max(dot(normal, -rd), 0.0), f0);
+
+  // Start the reflected ray clear of the surface
+  let rr = reflect(rd, normal);
+  let reflection = get_water_reflection(pos + normal * rp.epsilon * 2.0, rr, uv);
+  let refraction = get_water_refraction(pos, rd, uv, normal, water_depth);
+  var clr = mix(refraction, reflection, fresnel);
+
+  // Breaking waves along the shore, whitecaps on the crests in the shallows
+  let shore = 1.0 - smoothstep(0.0, wp.foam_width, water_depth);
+  let crest = smoothstep(0.6, 1.0, wave_crest);
+  let foam = shore * mix(0.35, 1.0, crest);
+
+  if (foam > 0.0) {
+    clr = mix(clr, get_light(pos, rd, uv, material, FOAM_CLR), foam);
+  }
+
+  return clr;
+}
+
 struct Terrain {
   dist: f32,
   water_depth: f32,
   crater_clr: f32,
+  wave_crest: f32,
 }
 
 fn get_terrain(pos: vec3<f32>, uv: vec2<f32>) -> Terrain {
@@ -326,6 +875,15 @@ fn get_terrain(pos: vec3<f32>, uv: vec2<f32>) -> Terrain {
 
   // Calc water depth for use in render
   let water_depth = max(0.0, d1 - d0);
+
+  // Only evaluate waves close to the water shell
+  var wave_crest = 0.0;
+  let wave_amp = get_wave_amplitude(water_depth);
+  if (wave_amp > 0.0 && abs(d0) < wave_amp * 2.0 + rp.epsilon) {
+    wave_crest = get_waves(rPos);
+    d0 -= wave_crest * wave_amp;
+  }
+
   // Cover lower elevations in water
   d1 = min(d0, d1);
   // If above 0.95 latitude add ice texture on water
@@ -334,20 +892,18 @@ fn get_terrain(pos: vec3<f32>, uv: vec2<f32>) -> Terrain {
   // Dont add extra texture to polar mountains
   let polar_flats_switch = step(length(rPos - CENTER), WATER_LEVEL);
   d1 += polar_flats_switch*ice_switch*tx.x*0.3;
-  var moon = get_moon(pos, uv);
-
-  d1 = min(moon.dist, d1);
   
-  return Terrain(d1, water_depth, moon.crater_clr);
+  return Terrain(d1, water_depth, 0.0, wave_crest);
 }
 
 fn map(pos: vec3<f32>, uv: vec2<f32>) -> Terrain {
-  var d = 0.0;
-  
-  let t = get_terrain(pos, uv);
-  d += t.dist;
+  var t = get_terrain(pos, uv);
+  let moon = get_moon(pos, uv);
+
+  t.dist = min(moon.dist, t.dist);
+  t.crater_clr = moon.crater_clr;
 
-  return Terrain(d, t.water_depth, t.crater_clr);
+  return t;
 }
 
 // RAY MARCHING
@@ -367,56 +923,748 @@ struct TerrainPos {
   dist: f32,
   water_depth: f32,
   crater_clr: f32,
+  wave_crest: f32,
   pos: vec3<f32>,
+  // How far past the surface the march landed before the hit was refined
+  overshoot: f32,
+  // Map evaluations spent on the ray, including the bisection
+  steps: f32,
+}
+
+// Steps through empty space use the distance to a body's bounding sphere
+// instead of evaluating its textured SDF
+fn map_bounded(pos: vec3<f32>, uv: vec2<f32>) -> Terrain {
+  var t = Terrain(length(pos) - PLANET_BOUND, 0.0, 0.0, 0.0);
+  if (t.dist < BOUND_MARGIN) {
+    t = get_terrain(pos, uv);
+  }
+
+  var moon = Moon(length(pos + get_moon_position()) - MOON_BOUND, 0.0);
+  if (moon.dist < BOUND_MARGIN) {
+    moon = get_moon(pos, uv);
+  }
+
+  t.dist = min(moon.dist, t.dist);
+  t.crater_clr = moon.crater_clr;
+
+  return t;
+}
+
+fn map_march(pos: vec3<f32>, uv: vec2<f32>, bounded: bool) -> Terrain {
+  if (bounded) {
+    return map_bounded(pos, uv);
+  }
+  return map(pos, uv);
+}
+
+// Half-angle covered by one pixel
+fn pixel_cone() -> f32 {
+  return tan(0.5 * cam.fov_y) / SCREEN_HEIGHT;
+}
+
+fn ray_march(ro: vec3<f32>, rd: vec3<f32>, uv: vec2<f32>) -> TerrainPos {
+  let far = min(rp.max_dist, cam.far);
+
+  if (rp.accelerate < 0.5) {
+    return sphere_trace(ro, rd, uv, cam.near, far, false);
+  }
+
+  let planet_span = ray_sphere(ro, rd, PLANET_BOUND);
+  let moon_span = ray_sphere(ro + get_moon_position(), rd, MOON_BOUND);
+
+  // Rays that miss both bounds never evaluate the SDF at all
+  let enter = max(min(planet_span.x, moon_span.x), cam.near);
+  let exit = min(max(planet_span.y, moon_span.y), far);
+
+  if (enter > exit) {
+    return TerrainPos(rp.max_dist, 0.0, 0.0, 0.0, ro + rd * rp.max_dist, 0.0, 0.0);
+  }
+
+  return sphere_trace(ro, rd, uv, enter, exit, true);
 }
 
-fn ray_march(ro: vec3<f32>, rd: vec3<f32>, uv: vec2<f32>, look_at: vec3<f32>) -> TerrainPos {
+// The displaced terrain is not a true distance field, its gradient exceeds 1.0
+// on steep relief. Steps are divided by a gradient bound that grows whenever the
+// ray sees the distance fall faster than it moved, and over-relaxed steps fall
+// back to plain ones as soon as consecutive spheres stop overlapping.
+fn sphere_trace(
+  ro: vec3<f32>,
+  rd: vec3<f32>,
+  uv: vec2<f32>,
+  enter: f32,
+  exit: f32,
+  bounded: bool,
+) -> TerrainPos {
+  var miss = TerrainPos(rp.max_dist, 0.0, 0.0, 0.0, ro + rd * rp.max_dist, 0.0, 0.0);
   let steps = i32(rp.max_steps);
-  var dist = 0.0;
-  var water_depth = 0.0;
-  var crater_clr = 0.0;
-  var p = vec3(0.0);
+  let cone = select(0.0, pixel_cone() * EPSILON_PIXEL_FRACTION, bounded);
+
+  var gradient_bound = max(rp.gradient_bound, 1.0);
+  var omega = max(rp.relaxation, 1.0);
+  var dist = enter;
+  // Last point known to be outside the surface, and its safe radius
+  var safe_dist = enter;
+  var safe_radius = 0.0;
+  var safe_signed = 0.0;
+  var last = TerrainPos(dist, 0.0, 0.0, 0.0, ro + rd * dist, 0.0, 0.0);
 
   for (var i: i32 = 0; i < steps; i++) {
     let pos = ro + dist * rd;
-    let t = map(pos, uv);
-    let hit = t.dist;
-    water_depth = t.water_depth;
-    crater_clr = t.crater_clr;
-    p = pos;
+    let t = map_march(pos, uv, bounded);
+    let eps = max(rp.epsilon, dist * cone);
+    last = TerrainPos(dist, t.water_depth, t.crater_clr, t.wave_crest, pos, 0.0, f32(i + 1));
+
+    // Landed inside, the surface lies between the last safe point and here
+    if (t.dist < 0.0 && i > 0) {
+      let overshoot = max(-t.dist - eps, 0.0);
+      return refine_hit(ro, rd, uv, safe_dist, dist, bounded, overshoot, f32(i + 1));
+    }
 
-    if (abs(hit) < rp.epsilon) {
-      break;
+    if (abs(t.dist) < eps) {
+      return last;
+    }
+
+    // Distance can't fall faster than the ray moves in a true distance field
+    if (i > 0 && dist > safe_dist) {
+      gradient_bound = max(gradient_bound, (safe_signed - t.dist) / (dist - safe_dist));
+    }
+
+    let radius = t.dist / gradient_bound;
+
+    // An over-relaxed step whose sphere doesn't overlap the last one may
+    // have jumped a thin ridge, redo it as a plain step
+    if (omega > 1.0 && i > 0 && radius + safe_radius < dist - safe_dist) {
+      omega = 1.0;
+      dist = safe_dist + safe_radius;
+      continue;
+    }
+
+    safe_dist = dist;
+    safe_radius = radius;
+    safe_signed = t.dist;
+    dist += radius * omega;
+
+    if (dist > exit) {
+      miss.steps = f32(i + 1);
+      return miss;
+    }
+  }
+
+  // Out of steps, grazing rays count as hits
+  return last;
+}
+
+// Bisection between a point outside the surface and one inside it
+fn refine_hit(
+  ro: vec3<f32>,
+  rd: vec3<f32>,
+  uv: vec2<f32>,
+  outside: f32,
+  inside: f32,
+  bounded: bool,
+  overshoot: f32,
+  steps: f32,
+) -> TerrainPos {
+  var a = outside;
+  var b = inside;
+
+  for (var i: i32 = 0; i < BISECTION_STEPS; i++) {
+    let mid = 0.5 * (a + b);
+    if (map_march(ro + rd * mid, uv, bounded).dist > 0.0) {
+      a = mid;
+    } else {
+      b = mid;
+    }
+  }
+
+  let dist = 0.5 * (a + b);
+  let pos = ro + rd * dist;
+  let t = map_march(pos, uv, bounded);
+  let total_steps = steps + f32(BISECTION_STEPS + 1);
+  return TerrainPos(dist, t.water_depth, t.crater_clr, t.wave_crest, pos, overshoot, total_steps);
+}
+
+// ATMOSPHERE
+// Near and far hit distances along rd, near > far when the ray misses
+fn ray_sphere(ro: vec3<f32>, rd: vec3<f32>, radius: f32) -> vec2<f32> {
+  let b = dot(ro, rd);
+  let c = dot(ro, ro) - radius * radius;
+  let h = b * b - c;
+
+  if (h < 0.0) {
+    return vec2(MAX_F32, -MAX_F32);
+  }
+
+  let sh = sqrt(h);
+  return vec2(-b - sh, -b + sh);
+}
+
+fn rayleigh_phase(mu: f32) -> f32 {
+  return 3.0 / (16.0 * PI) * (1.0 + mu * mu);
+}
+
+// Cornette-Shanks phase function, g > 0.0 scatters forwards
+fn mie_phase(mu: f32, g: f32) -> f32 {
+  let g2 = g * g;
+  let denom = (2.0 + g2) * pow(max(1.0 + g2 - 2.0 * g * mu, 0.0001), 1.5);
+  return 3.0 / (8.0 * PI) * (1.0 - g2) * (1.0 + mu * mu) / denom;
+}
+
+fn rayleigh_coefficients() -> vec3<f32> {
+  return vec3(ap.rayleigh_r, ap.rayleigh_g, ap.rayleigh_b) * ap.density;
+}
+
+fn mie_coefficients() -> vec3<f32> {
+  return vec3(ap.mie_coefficient * ap.density);
+}
+
+// Rayleigh (x) and Mie (y) densities relative to PLANET_RADIUS
+fn atmosphere_density(pos: vec3<f32>) -> vec2<f32> {
+  let h = max(length(pos) - PLANET_RADIUS, 0.0);
+  return vec2(exp(-h / ap.rayleigh_scale_height), exp(-h / ap.mie_scale_height));
+}
+
+fn atmosphere_extinction(optical_depth: vec2<f32>) -> vec3<f32> {
+  return rayleigh_coefficients() * optical_depth.x
+    + mie_coefficients() * MIE_EXTINCTION * optical_depth.y;
+}
+
+// Optical depth from pos to the top of the atmosphere along l
+fn light_optical_depth(pos: vec3<f32>, l: vec3<f32>) -> vec2<f32> {
+  let hit = ray_sphere(pos, l, ap.outer_radius);
+  let t0 = max(hit.x, 0.0);
+  let t1 = hit.y;
+
+  if (t1 <= t0) {
+    return vec2(0.0);
+  }
+
+  let seg = (t1 - t0) / f32(ATMOSPHERE_LIGHT_SAMPLES);
+  var depth = vec2(0.0);
+
+  for (var i: i32 = 0; i < ATMOSPHERE_LIGHT_SAMPLES; i++) {
+    depth += atmosphere_density(pos + l * (t0 + (f32(i) + 0.5) * seg)) * seg;
+  }
+
+  return depth;
+}
+
+// Sunlight reaching pos, after eclipses and the atmosphere
+fn get_sun_transmittance(pos: vec3<f32>) -> vec3<f32> {
+  let l = normalize(get_light_pos() - pos);
+  let eclipse = get_eclipse(pos, l);
+
+  if (ap.density <= 0.0) {
+    return vec3(eclipse);
+  }
+
+  return eclipse * exp(-atmosphere_extinction(light_optical_depth(pos, l)));
+}
+
+struct Atmosphere {
+  inscatter: vec3<f32>,
+  transmittance: vec3<f32>,
+}
+
+// Single scattering along the view ray up to max_t. Samples in the
+// planet's shadow get no sunlight, which darkens the night side.
+fn get_atmosphere(ro: vec3<f32>, rd: vec3<f32>, max_t: f32) -> Atmosphere {
+  var atmosphere = Atmosphere(vec3(0.0), vec3(1.0));
+
+  if (ap.density <= 0.0) {
+    return atmosphere;
+  }
+
+  let hit = ray_sphere(ro, rd, ap.outer_radius);
+  let t0 = max(hit.x, 0.0);
+  let t1 = min(hit.y, max_t);
+
+  if (t1 <= t0) {
+    return atmosphere;
+  }
+
+  let light_pos = get_light_pos();
+  let seg = (t1 - t0) / f32(ATMOSPHERE_VIEW_SAMPLES);
+  var view_depth = vec2(0.0);
+  var rayleigh = vec3(0.0);
+  var mie = vec3(0.0);
+
+  for (var i: i32 = 0; i < ATMOSPHERE_VIEW_SAMPLES; i++) {
+    let pos = ro + rd * (t0 + (f32(i) + 0.5) * seg);
+    let density = atmosphere_density(pos) * seg;
+    view_depth += density;
+
+    let l = normalize(light_pos - pos);
+    let occluder = ray_sphere(pos, l, PLANET_RADIUS);
+    if (occluder.x > 0.0 && occluder.x < occluder.y) {
+      continue;
     }
-    dist += hit;
 
-    if (dist > rp.max_dist) {
+    let light_depth = light_optical_depth(pos, l);
+    let attenuation = exp(-atmosphere_extinction(view_depth + light_depth));
+    rayleigh += density.x * attenuation;
+    mie += density.y * attenuation;
+  }
+
+  let mid = ro + rd * (t0 + t1) * 0.5;
+  let mu = dot(rd, normalize(light_pos - mid));
+
+  atmosphere.inscatter = ap.sun_intensity * get_sun_color() * (
+    rayleigh * rayleigh_coefficients() * rayleigh_phase(mu)
+    + mie * mie_coefficients() * mie_phase(mu, ap.mie_g)
+  );
+  atmosphere.transmittance = exp(-atmosphere_extinction(view_depth));
+
+  return atmosphere;
+}
+
+// CLOUDS
+fn hash13(p: vec3<f32>) -> f32 {
+  var q = fract(p * 0.1031);
+  q += dot(q, q.zyx + 31.32);
+  return fract((q.x + q.y) * q.z);
+}
+
+fn value_noise3(p: vec3<f32>) -> f32 {
+  let i = floor(p);
+  let f = fract(p);
+  let u = f * f * (3.0 - 2.0 * f);
+
+  let x00 = mix(hash13(i), hash13(i + vec3(1.0, 0.0, 0.0)), u.x);
+  let x10 = mix(hash13(i + vec3(0.0, 1.0, 0.0)), hash13(i + vec3(1.0, 1.0, 0.0)), u.x);
+  let x01 = mix(hash13(i + vec3(0.0, 0.0, 1.0)), hash13(i + vec3(1.0, 0.0, 1.0)), u.x);
+  let x11 = mix(hash13(i + vec3(0.0, 1.0, 1.0)), hash13(i + vec3(1.0, 1.0, 1.0)), u.x);
+
+  return mix(mix(x00, x10, u.y), mix(x01, x11, u.y), u.z);
+}
+
+fn cloud_fbm(p: vec3<f32>) -> f32 {
+  var q = p;
+  var value = 0.0;
+  var amp = 0.5;
+
+  for (var i: i32 = 0; i < 5; i++) {
+    value += amp * value_noise3(q);
+    q = q * 2.03 + vec3(1.7, 9.2, 3.1);
+    amp *= 0.5;
+  }
+
+  return value / 0.96875;
+}
+
+// Inner and outer radius of the cloud shell
+fn cloud_layer() -> vec2<f32> {
+  let base = PLANET_RADIUS + cp.altitude;
+  return vec2(base, base + cp.thickness);
+}
+
+fn get_cloud_density(pos: vec3<f32>) -> f32 {
+  let layer = cloud_layer();
+  let r = length(pos);
+
+  if (r < layer.x || r > layer.y) {
+    return 0.0;
+  }
+
+  // Flat-ish bases, rounded tops
+  let h = (r - layer.x) / cp.thickness;
+  let profile = smoothstep(0.0, 0.15, h) * (1.0 - smoothstep(0.5, 1.0, h));
+
+  // Clouds turn with the planet, the wind carries them further and
+  // the vertical drift slowly reshapes them
+  let cPos = rotate3d(pos, 0.0, (PLANET_ROTATION + cp.wind_speed) * tu.time);
+  let drift = vec3(0.0, tu.time * cp.wind_speed * 5.0, 0.0);
+  let n = cloud_fbm(cPos * cp.noise_scale + drift);
+
+  let cover = clamp((n - (1.0 - cp.coverage)) * 4.0, 0.0, 1.0);
+  return cover * profile * cp.density;
+}
+
+// Transmittance through the cloud shell from pos towards the light
+fn get_cloud_shadow(pos: vec3<f32>, l: vec3<f32>) -> f32 {
+  if (cp.enabled < 0.5) {
+    return 1.0;
+  }
+
+  let layer = cloud_layer();
+  let outer = ray_sphere(pos, l, layer.y);
+  var t0 = max(outer.x, 0.0);
+  let t1 = outer.y;
+
+  // Start marching where the ray leaves the inner sphere
+  if (length(pos) < layer.x) {
+    t0 = max(t0, ray_sphere(pos, l, layer.x).y);
+  }
+
+  if (t1 <= t0) {
+    return 1.0;
+  }
+
+  let seg = (t1 - t0) / f32(CLOUD_LIGHT_SAMPLES);
+  var depth = 0.0;
+
+  for (var i: i32 = 0; i < CLOUD_LIGHT_SAMPLES; i++) {
+    depth += get_cloud_density(pos + l * (t0 + (f32(i) + 0.5) * seg)) * seg;
+  }
+
+  return exp(-depth);
+}
+
+struct Clouds {
+  color: vec3<f32>,
+  transmittance: f32,
+}
+
+fn get_clouds(ro: vec3<f32>, rd: vec3<f32>, max_t: f32) -> Clouds {
+  var clouds = Clouds(vec3(0.0), 1.0);
+
+  if (cp.enabled < 0.5) {
+    return clouds;
+  }
+
+  let outer = ray_sphere(ro, rd, cloud_layer().y);
+  let t0 = max(outer.x, 0.0);
+  let t1 = min(outer.y, max_t);
+
+  if (t1 <= t0) {
+    return clouds;
+  }
+
+  let light_pos = get_light_pos();
+  let seg = (t1 - t0) / f32(CLOUD_VIEW_SAMPLES);
+
+  // Mostly isotropic with a forward lobe for silver linings
+  let mu = dot(rd, normalize(light_pos - ro));
+  let phase = 0.7 + 0.3 * 4.0 * PI * mie_phase(mu, 0.6);
+
+  for (var i: i32 = 0; i < CLOUD_VIEW_SAMPLES; i++) {
+    let pos = ro + rd * (t0 + (f32(i) + 0.5) * seg);
+    let density = get_cloud_density(pos);
+
+    if (density <= 0.0) {
+      continue;
+    }
+
+    let l = normalize(light_pos - pos);
+    let occluder = ray_sphere(pos, l, PLANET_RADIUS);
+    var sun = get_sun_transmittance(pos) * get_sun_color() * get_cloud_shadow(pos, l)
+      * get_ring_shadow(pos, l);
+    if (occluder.x > 0.0 && occluder.x < occluder.y) {
+      sun = vec3(0.0);
+    }
+
+    let step_transmittance = exp(-density * seg);
+    let scatter = sun * phase * CLOUD_ALBEDO + CLOUD_AMBIENT;
+    clouds.color += clouds.transmittance * scatter * (1.0 - step_transmittance);
+    clouds.transmittance *= step_transmittance;
+
+    if (clouds.transmittance < 0.01) {
       break;
     }
   }
 
-  return TerrainPos(dist, water_depth, crater_clr, p);
+  return clouds;
 }
 
-// RENDERING
-fn render(uv: vec2<f32>) -> vec3<f32> {
-  var ro: vec3<f32> = vec3(0.0, 0.0, -300.0);
-  ro = rotate3d(ro, vp.y_rot, vp.x_rot);
+// RINGS
+// The ring plane is the equator tilted around the x axis
+fn ring_normal() -> vec3<f32> {
+  return vec3(0.0, cos(rings.tilt), sin(rings.tilt));
+}
+
+// Distance along rd to the ring plane, negative when the ray never crosses it
+fn ring_plane_hit(ro: vec3<f32>, rd: vec3<f32>) -> f32 {
+  let n = ring_normal();
+  let denom = dot(rd, n);
 
-  let look_at: vec3<f32> = vec3(0.0, 0.0, 0.0);
+  if (abs(denom) < 0.000001) {
+    return -1.0;
+  }
+
+  return -dot(ro, n) / denom;
+}
+
+// 0.0 -> 1.0 from the inner to the outer edge
+fn ring_coord(r: f32) -> f32 {
+  return (r - rings.inner_radius) / max(rings.outer_radius - rings.inner_radius, 0.001);
+}
+
+// Opacity at radius r, bands come from 1D noise sharpened so gaps open between them
+fn ring_density(r: f32) -> f32 {
+  let x = ring_coord(r);
+
+  if (x < 0.0 || x > 1.0) {
+    return 0.0;
+  }
+
+  var bands = 0.0;
+  var amp = 0.5;
+  var freq = rings.band_frequency;
+  for (var i: i32 = 0; i < RING_BAND_OCTAVES; i++) {
+    bands += amp * value_noise3(vec3(x * freq, f32(i) * 7.31, 0.0));
+    amp *= 0.5;
+    freq *= 2.3;
+  }
+
+  let banded = smoothstep(0.3, 0.7, bands / 0.9375);
+  let edges = smoothstep(0.0, RING_EDGE_FADE, x)
+    * (1.0 - smoothstep(1.0 - RING_EDGE_FADE, 1.0, x));
+  return clamp(banded * edges * rings.opacity, 0.0, 1.0);
+}
+
+fn ring_color(r: f32) -> vec3<f32> {
+  let inner = vec3(rings.inner_r, rings.inner_g, rings.inner_b);
+  let outer = vec3(rings.outer_r, rings.outer_g, rings.outer_b);
+  return mix(inner, outer, clamp(ring_coord(r), 0.0, 1.0));
+}
 
-  var rd: vec3<f32> = (get_cam(ro, look_at) * normalize(vec4(uv * FOV, 1.0, 0.0))).xyz;
-  let terrain = ray_march(ro, rd, uv, look_at);
+// Transmittance through the rings from pos towards the light
+fn get_ring_shadow(pos: vec3<f32>, l: vec3<f32>) -> f32 {
+  if (rings.enabled < 0.5) {
+    return 1.0;
+  }
+
+  let t = ring_plane_hit(pos, l);
+  if (t <= 0.0) {
+    return 1.0;
+  }
+
+  return 1.0 - ring_density(length(pos + l * t));
+}
+
+struct Rings {
+  color: vec3<f32>,
+  transmittance: f32,
+  // Seen through the atmosphere, so it's composited before the clouds and atmosphere
+  behind: bool,
+}
+
+fn get_rings(ro: vec3<f32>, rd: vec3<f32>, max_t: f32) -> Rings {
+  var result = Rings(vec3(0.0), 1.0, false);
+
+  if (rings.enabled < 0.5) {
+    return result;
+  }
+
+  let t = ring_plane_hit(ro, rd);
+  if (t <= 0.0 || t >= max_t) {
+    return result;
+  }
+
+  let pos = ro + rd * t;
+  let r = length(pos);
+  let alpha = ring_density(r);
+  if (alpha <= 0.0) {
+    return result;
+  }
+
+  // The planet's shadow falls across the rings on its night side
+  let l = normalize(get_light_pos() - pos);
+  let shadow = sphere_occlusion(pos, l, CENTER, PLANET_RADIUS);
+
+  // Sunlight on the far face only reaches the viewer through the gaps between particles,
+  // the forward lobe makes the rings glow when backlit
+  let n = ring_normal();
+  var sun = get_sun_color() * shadow;
+  if (sign(dot(n, l)) != sign(dot(n, -rd))) {
+    sun *= 1.0 - alpha;
+  }
+  let phase = 4.0 * PI * mie_phase(dot(rd, l), rings.scatter_g);
+
+  let clr = ring_color(r);
+  result.color = (sun * phase * RING_ALBEDO + RING_AMBIENT) * clr * alpha;
+  result.transmittance = 1.0 - alpha;
+
+  // The rings lie outside the atmosphere, so a hit past where the ray enters it is behind it
+  let atmosphere = ray_sphere(ro, rd, ap.outer_radius);
+  result.behind = atmosphere.x < atmosphere.y && t > max(atmosphere.x, 0.0);
+
+  return result;
+}
+
+// BACKGROUND
+// 0.0 on the galactic plane, 1.0 at its poles
+fn galactic_latitude(rd: vec3<f32>) -> f32 {
+  return abs(dot(rd, normalize(GALACTIC_NORTH)));
+}
+
+fn get_nebula(rd: vec3<f32>) -> vec3<f32> {
+  let band = exp(-pow(galactic_latitude(rd) / NEBULA_WIDTH, 2.0));
+  let dust = cloud_fbm(rd * 6.0);
+  let glow = cloud_fbm(rd * 2.5 + vec3(4.3, 1.1, 7.9));
+
+  let clr = mix(NEBULA_CLR1, NEBULA_CLR2, glow);
+  // Dark lanes cut through the brightest part of the band
+  let lanes = smoothstep(0.35, 0.65, dust);
+  return clr * band * lanes * bp.nebula_intensity * bp.nebula_enabled;
+}
+
+// One possible star per cell of a grid wrapped around the unit sphere,
+// stars are fixed to world directions so they don't move with the camera
+fn get_stars(rd: vec3<f32>) -> vec3<f32> {
+  let p = rd * bp.star_scale;
+  let cell = floor(p);
+
+  // Stars crowd into the Milky Way
+  let band = exp(-pow(galactic_latitude(rd) / NEBULA_WIDTH, 2.0)) * bp.nebula_enabled;
+  let density = bp.star_density * (1.0 + 2.0 * band);
+
+  if (hash13(cell) > density) {
+    return vec3(0.0);
+  }
+
+  let offset = vec3(hash13(cell + 17.1), hash13(cell + 31.7), hash13(cell + 53.3));
+  let star = normalize(cell + 0.1 + offset * 0.8) * bp.star_scale;
+  let d = length(p - star) / STAR_RADIUS;
+
+  // Faint stars far outnumber bright ones
+  let h = hash13(cell + 71.9);
+  let magnitude = STAR_MAX_MAGNITUDE * (1.0 - h * h);
+  let brightness = pow(2.512, -magnitude);
+
+  let temperature = hash13(cell + 97.3);
+  var clr = mix(STAR_CLR_COOL, vec3(1.0), smoothstep(0.0, 0.5, temperature));
+  clr = mix(clr, STAR_CLR_HOT, smoothstep(0.6, 1.0, temperature));
+
+  return clr * brightness * exp(-d * d) * bp.star_brightness;
+}
+
+// Equirectangular, longitude around the planet's axis and latitude from the north pole
+fn get_env_map(rd: vec3<f32>) -> vec3<f32> {
+  let longitude = atan2(rd.x, -rd.z) + bp.env_rotation;
+  let latitude = acos(clamp(rd.y, -1.0, 1.0));
+  let uv = vec2(longitude / (2.0 * PI) + 0.5, latitude / PI);
+  return textureSampleLevel(env_tex, env_sampler, uv, 0.0).rgb * bp.env_intensity;
+}
+
+// Everything behind the planet and moon except the sun
+fn get_sky(rd: vec3<f32>) -> vec3<f32> {
+  if (bp.env_map_enabled > 0.5) {
+    return get_env_map(rd);
+  }
+  return get_stars(rd) + get_nebula(rd);
+}
+
+fn get_sun_disc(ro: vec3<f32>, rd: vec3<f32>) -> vec3<f32> {
+  let l = normalize(get_light_pos() - ro);
+  let angle = acos(clamp(dot(rd, l), -1.0, 1.0));
+  let r = max(lp.angular_radius, 0.0001);
+
+  // Limb darkened disc plus a faint corona
+  let x = clamp(angle / r, 0.0, 1.0);
+  let limb = 1.0 - 0.6 * (1.0 - sqrt(1.0 - x * x));
+  let disc = (1.0 - smoothstep(0.9, 1.0, angle / r)) * limb;
+  let corona = exp(-angle / (r * 2.0)) * 0.05;
+
+  return get_sun_color() * (disc + corona) * bp.sun_disc_intensity;
+}
+
+fn get_background(ro: vec3<f32>, rd: vec3<f32>) -> vec3<f32> {
+  return get_sky(rd) + get_sun_disc(ro, rd);
+}
+
+// DEBUG VIEWS
+// Blue -> green -> red as x goes 0.0 -> 1.0
+fn debug_ramp(x: f32) -> vec3<f32> {
+  let t = clamp(x, 0.0, 1.0);
+  return vec3(smoothstep(0.4, 0.9, t), sin(PI * t), 1.0 - smoothstep(0.1, 0.6, t));
+}
+
+fn debug_material(m: MaterialEnum) -> vec3<f32> {
+  let total = m.ice + m.water + m.rock + m.plant + m.sand + m.moon;
+  let c = ICE_CLR * m.ice
+    + DEBUG_WATER_CLR * m.water
+    + ROCK_CLR * 2.0 * m.rock
+    + PLANT_CLR1 * m.plant
+    + SAND_CLR1 * m.sand
+    + DEBUG_MOON_CLR * m.moon;
+  return c / max(total, 0.0001);
+}
+
+// Planet texture at the hit with the same triplanar blend the terrain uses
+fn debug_planet_texel(pos: vec3<f32>, uv: vec2<f32>) -> vec4<f32> {
+  let rPos = rotate3d(pos, 0.0, PLANET_ROTATION * tu.time);
+  return tex_triplanar_mapping(rPos, uv, PLANET_RADIUS, 1.0, planet_tex, planet_sampler);
+}
+
+fn get_debug_view(terrain: TerrainPos, uv: vec2<f32>, material: MaterialEnum, shaded: vec3<f32>) -> vec3<f32> {
+  let view = u32(dbg.view);
+  let hit = terrain.dist < rp.max_dist;
+
+  // Misses cost steps too, every other view only describes hits
+  if (view == DEBUG_VIEW_STEPS) {
+    return debug_ramp(terrain.steps / max(dbg.step_range, 1.0));
+  }
+
+  if (view == DEBUG_VIEW_OVERSHOOT) {
+    let shade = select(0.0, 0.3 + 0.7 * smoothstep(0.0, OVERSHOOT_DEBUG_DEPTH, terrain.overshoot), terrain.overshoot > 0.0);
+    return mix(shaded, OVERSHOOT_DEBUG_CLR, shade);
+  }
+
+  if (!hit) {
+    return vec3(0.0);
+  }
+
+  switch view {
+    case DEBUG_VIEW_NORMALS: {
+      return get_normal(terrain.pos, uv) * 0.5 + 0.5;
+    }
+    case DEBUG_VIEW_SLOPE: {
+      return debug_ramp(calculate_slope(terrain.pos, uv) / DEBUG_MAX_SLOPE);
+    }
+    case DEBUG_VIEW_MATERIAL: {
+      return debug_material(material);
+    }
+    case DEBUG_VIEW_WATER_DEPTH: {
+      if (terrain.water_depth <= 0.0) {
+        return vec3(0.05);
+      }
+      return debug_ramp(terrain.water_depth / DEBUG_MAX_WATER_DEPTH);
+    }
+    case DEBUG_VIEW_HEIGHTMAP: {
+      let texel = debug_planet_texel(terrain.pos, uv);
+      let channel = clamp(i32(dbg.heightmap_channel), 0, 3);
+      return vec3(texel[channel]);
+    }
+    case DEBUG_VIEW_WAVE_DIRECTION: {
+      let dir = debug_planet_texel(terrain.pos, uv).yz;
+      return vec3(dir * 0.5 + 0.5, 0.0);
+    }
+    case DEBUG_VIEW_TRIPLANAR: {
+      // Same weights as tex_triplanar_mapping, red YZ, green XZ, blue XY
+      let rPos = rotate3d(terrain.pos, 0.0, PLANET_ROTATION * tu.time);
+      var n = abs(get_normal_rm(rPos, PLANET_RADIUS));
+      n *= n*n*n*n*n;
+      return n / (n.x + n.y + n.z);
+    }
+    default: {
+      return shaded;
+    }
+  }
+}
+
+// RENDERING
+struct Sample {
+  color: vec3<f32>,
+  // World position of the hit, or the ray direction for rays that hit nothing
+  pos: vec3<f32>,
+  hit: bool,
+}
+
+fn render(uv: vec2<f32>) -> Sample {
+  let ro = camera_position();
+  let rd = camera_ray(uv, camera_rotation(), cam.fov_y);
+  let terrain = ray_march(ro, rd, uv);
   let dist: f32 = terrain.dist;
   let wd = terrain.water_depth;
   let crater = terrain.crater_clr;
-  let steepness = calculate_slope(terrain.pos, uv);
 
   let cam_pos = ro + dist * rd;
   var col: vec3<f32> = vec3(0.0);
   var material = MaterialEnum(0.0, 0.0, 0.0, 0.0, 0.0, 0.0);
 
   if (dist < rp.max_dist) {
+    let steepness = calculate_slope(terrain.pos, uv);
     let dist_origin: f32 = length(cam_pos);
     let latitude = abs(cam_pos.y / WATER_LEVEL);
     let adjusted_ice_level = ICE_LEVEL - latitude*1.8;
@@ -424,25 +1672,22 @@ fn render(uv: vec2<f32>) -> vec3<f32> {
     // ICE
     if dist_origin > PLANET_LIMIT {
       material.moon += 1.0;
-      col += max(vec3(0.2), get_light(cam_pos, rd, uv, material)*ICE_CLR - crater);
+      col += max(vec3(0.2), get_light(cam_pos, rd, uv, material, ICE_CLR) - crater);
     } else if dist_origin > adjusted_ice_level {
       let ef = smoothstep(adjusted_ice_level, adjusted_ice_level + 1.0, dist_origin);
       let ice_clr = mix(ROCK_CLR, ICE_CLR, ef);
       material.ice = 1.0*ef;
       material.rock = 1.0 - 1.0*ef;
-      col += get_light(cam_pos, rd, uv, material)*ice_clr;
+      col += get_light(cam_pos, rd, uv, material, ice_clr);
     } else if latitude > 0.95 {
       let ef = smoothstep(0.95, 0.96, latitude);
       let ice_clr = mix(ROCK_CLR, ICE_CLR, ef);
       material.ice = 1.0*ef;
-      col += get_light(cam_pos, rd, uv, material)*ice_clr;
+      col += get_light(cam_pos, rd, uv, material, ice_clr);
     // UNDERWATER
     } else if dist_origin < WATER_LEVEL {
-      let rg = max(0.0, (1.0 - wd)*0.05);
-      let b = 1.0 - 0.15*wd;
-      let water_clr = vec3(rg, rg, b);
       material.water = 1.0;
-      col += get_light(cam_pos, rd, uv, material)*water_clr;
+      col += get_water(cam_pos, rd, uv, material, wd, terrain.wave_crest);
     // BEACHES
     } else if (
       dist_origin < SAND_LEVEL
@@ -451,7 +1696,7 @@ fn render(uv: vec2<f32>) -> vec3<f32> {
       let ef = smoothstep(SAND_LEVEL - 0.15, SAND_LEVEL, dist_origin);
       let beach_mix = mix(SAND_CLR1, SAND_CLR2, ef);
       material.sand = 1.0;
-      col += get_light(cam_pos, rd, uv, material)*beach_mix;
+      col += get_light(cam_pos, rd, uv, material, beach_mix);
     // PLANTS
     } else if (
       dist_origin < PLANT_LEVEL - latitude*1.4 
@@ -466,7 +1711,7 @@ fn render(uv: vec2<f32>) -> vec3<f32> {
       let sp_mix2 = mix(sp_mix, EARTH_CLR, steep_mixer*0.4);
       
       material.plant = 1.0;
-      col += get_light(cam_pos, rd, uv, material)*(sp_mix2 + hp_mix2)*0.5;
+      col += get_light(cam_pos, rd, uv, material, (sp_mix2 + hp_mix2)*0.5);
     // EARTH/ROCK
     } else {
       let low_steep = step(dist_origin, PLANT_LEVEL)*(steepness - PLANT_THRESHOLD)*0.04;
@@ -474,27 +1719,108 @@ fn render(uv: vec2<f32>) -> vec3<f32> {
       let er_mixer = low_steep + high_altitude;
       let mountain_clr = mix(EARTH_CLR, ROCK_CLR, er_mixer);
       material.rock = 1.0;
-      col += get_light(cam_pos, rd, uv, material)*mountain_clr;
+      col += get_light(cam_pos, rd, uv, material, mountain_clr);
     }
+  } else {
+    col = get_background(ro, rd);
   }
-  
-  return col;
+
+  // Rays that hit nothing pass all the way through the clouds and atmosphere
+  var max_t = MAX_F32;
+  if (dist < rp.max_dist) {
+    max_t = dist;
+  }
+  // Rings beyond the limb are seen through the clouds and atmosphere, rings in front cover them
+  let ring = get_rings(ro, rd, max_t);
+  if (ring.behind) {
+    col = col * ring.transmittance + ring.color;
+  }
+
+  let clouds = get_clouds(ro, rd, max_t);
+  col = col * clouds.transmittance + clouds.color;
+
+  let atmosphere = get_atmosphere(ro, rd, max_t);
+  col = col * atmosphere.transmittance + atmosphere.inscatter;
+
+  if (!ring.behind) {
+    col = col * ring.transmittance + ring.color;
+  }
+
+  if (u32(dbg.view) != DEBUG_VIEW_OFF) {
+    col = get_debug_view(terrain, uv, material, col);
+  }
+
+  if (dist < rp.max_dist) {
+    return Sample(col, cam_pos, true);
+  }
+  return Sample(col, rd, false);
 }
 
-@fragment
-fn main(@builtin(position) FragCoord: vec4<f32>) -> @location(0) vec4<f32> {
-  let t: f32 = tu.time * vp.time_modifier;
-  var uv: vec2<f32> = scale_aspect(FragCoord.xy); // Scale to -1.0 -> 1.0 + fix aspect ratio
-  let uv0 = uv;
-  uv.x += vp.x_shift * vp.zoom;
-  uv.y += vp.y_shift * vp.zoom;
-  uv /= vp.zoom;
+// ANTI-ALIASING
+// Where the sample was on screen last frame, planet hits turn with the planet
+// and moon hits follow its orbit
+fn previous_screen_pos(s: Sample) -> vec2<f32> {
+  let ro = vec3(aa.prev_position_x, aa.prev_position_y, aa.prev_position_z);
+  let rotation = vec4(aa.prev_rotation_x, aa.prev_rotation_y, aa.prev_rotation_z, aa.prev_rotation_w);
+
+  var d = s.pos;
+  if (s.hit) {
+    var prev_pos = s.pos;
+    if (length(s.pos) > PLANET_LIMIT) {
+      prev_pos += get_moon_position() - get_moon_position_at(aa.prev_time);
+    } else {
+      prev_pos = rotate3d(s.pos, 0.0, PLANET_ROTATION * (tu.time - aa.prev_time));
+    }
+    d = normalize(prev_pos - ro);
+  }
+
+  let local = quat_rotate(quat_conjugate(rotation), d);
+  let uv = local.xy / (max(local.z, 0.0001) * tan(0.5 * aa.prev_fov_y) * ASPECT);
+  return unscale_aspect(uv);
+}
 
+// NxN rays spread evenly over the pixel
+fn render_supersampled(fc: vec2<f32>) -> vec3<f32> {
+  let n = clamp(i32(aa.ssaa_samples), 1, 4);
   var color = vec3(0.0);
+
+  for (var y: i32 = 0; y < n; y++) {
+    for (var x: i32 = 0; x < n; x++) {
+      let offset = (vec2(f32(x), f32(y)) + 0.5) / f32(n) - 0.5;
+      color += render(scale_aspect(fc + offset)).color;
+    }
+  }
+
+  return color / f32(n * n);
+}
+
+struct FragOutput {
+  @location(0) color: vec4<f32>,
+  // Screen uv offset since the last frame, read by the TAA resolve
+  @location(1) motion: vec2<f32>,
+}
+
+@fragment
+fn main(@builtin(position) FragCoord: vec4<f32>) -> FragOutput {
+  var out: FragOutput;
+  out.motion = vec2(0.0);
 // -----------------------------------------------------------------------------------------------
 
-  color = render(uv);
+  switch u32(aa.mode) {
+    case AA_SSAA: {
+      out.color = vec4(render_supersampled(FragCoord.xy), 1.0);
+    }
+    case AA_TAA: {
+      let jitter = vec2(aa.jitter_x, aa.jitter_y);
+      let s = render(scale_aspect(FragCoord.xy + jitter));
+      out.color = vec4(s.color, 1.0);
+      out.motion = (FragCoord.xy - previous_screen_pos(s)) / vec2(SCREEN_WIDTH, SCREEN_HEIGHT);
+    }
+    default: {
+      out.color = vec4(render(scale_aspect(FragCoord.xy)).color, 1.0);
+    }
+  }
 
 // -----------------------------------------------------------------------------------------------
-  return vec4<f32>(color, 1.0);
+  return out;
 }
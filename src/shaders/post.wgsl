@@ -0,0 +1,218 @@
+const TONEMAP_NONE: u32 = 0u;
+const TONEMAP_ACES: u32 = 1u;
+const TONEMAP_REINHARD: u32 = 2u;
+const TONEMAP_AGX: u32 = 3u;
+
+// AA_* is injected from consts.rs
+
+// 9 tap gaussian folded into 5 bilinear fetches
+const BLUR_OFFSETS: array<f32, 3> = array<f32, 3>(0.0, 1.3846153846, 3.2307692308);
+const BLUR_WEIGHTS: array<f32, 3> = array<f32, 3>(0.2270270270, 0.3162162162, 0.0702702703);
+
+// Inset and outset matrices from the AgX reference implementation, column major
+const AGX_MAT: mat3x3<f32> = mat3x3(
+  0.842479062253094, 0.0423282422610123, 0.0423756549057051,
+  0.0784335999999992, 0.878468636469772, 0.0784336,
+  0.0792237451477643, 0.0791661274605434, 0.879142973793104,
+);
+
+const AGX_MAT_INV: mat3x3<f32> = mat3x3(
+  1.19687900512017, -0.0528968517574562, -0.0529716355144438,
+  -0.0980208811401368, 1.15190312990417, -0.0980434501171241,
+  -0.0990297440797205, -0.0989611768448433, 1.15107367264116,
+);
+
+const AGX_MIN_EV: f32 = -12.47393;
+const AGX_MAX_EV: f32 = 4.026069;
+
+struct PostParams {
+  exposure_enabled: f32,
+  exposure: f32,
+  tonemapper: f32,
+  bloom_enabled: f32,
+  bloom_threshold: f32,
+  bloom_intensity: f32,
+  srgb_enabled: f32,
+}
+
+// Only the leading fields of frag.wgsl's AntiAliasParams are read here
+struct AntiAliasParams {
+  mode: f32,
+  ssaa_samples: f32,
+  taa_blend: f32,
+  history_valid: f32,
+}
+
+struct VertexOutput {
+  @builtin(position) position: vec4<f32>,
+  @location(0) uv: vec2<f32>,
+}
+
+@group(0) @binding(0) var<storage, read> pp: PostParams;
+@group(0) @binding(1) var post_sampler: sampler;
+@group(0) @binding(2) var source_tex: texture_2d<f32>;
+@group(0) @binding(3) var bloom_tex: texture_2d<f32>;
+@group(0) @binding(4) var history_tex: texture_2d<f32>;
+@group(0) @binding(5) var motion_tex: texture_2d<f32>;
+@group(0) @binding(6) var<storage, read> aa: AntiAliasParams;
+
+// One triangle covering the screen, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) idx: u32) -> VertexOutput {
+  let uv = vec2(f32((idx << 1u) & 2u), f32(idx & 2u));
+  var out: VertexOutput;
+  out.position = vec4(uv * vec2(2.0, -2.0) + vec2(-1.0, 1.0), 0.0, 1.0);
+  out.uv = uv;
+  return out;
+}
+
+fn luminance(c: vec3<f32>) -> f32 {
+  return dot(c, vec3(0.2126, 0.7152, 0.0722));
+}
+
+// TEMPORAL ANTI-ALIASING
+// Blends the jittered frame into the reprojected history, the history is
+// clamped to the current 3x3 neighbourhood so disocclusions don't ghost
+@fragment
+fn fs_taa(in: VertexOutput) -> @location(0) vec4<f32> {
+  let current = textureSampleLevel(source_tex, post_sampler, in.uv, 0.0).rgb;
+
+  if (u32(aa.mode) != AA_TAA || aa.history_valid < 0.5) {
+    return vec4(current, 1.0);
+  }
+
+  let size = vec2<i32>(textureDimensions(source_tex));
+  let px = vec2<i32>(in.position.xy);
+  var lo = current;
+  var hi = current;
+
+  for (var y: i32 = -1; y <= 1; y++) {
+    for (var x: i32 = -1; x <= 1; x++) {
+      let q = clamp(px + vec2(x, y), vec2(0), size - 1);
+      let c = textureLoad(source_tex, q, 0).rgb;
+      lo = min(lo, c);
+      hi = max(hi, c);
+    }
+  }
+
+  let motion = textureLoad(motion_tex, px, 0).xy;
+  let prev_uv = in.uv - motion;
+
+  if (any(prev_uv < vec2(0.0)) || any(prev_uv > vec2(1.0))) {
+    return vec4(current, 1.0);
+  }
+
+  let history = clamp(textureSampleLevel(history_tex, post_sampler, prev_uv, 0.0).rgb, lo, hi);
+  return vec4(mix(current, history, aa.taa_blend), 1.0);
+}
+
+// BLOOM
+// Keeps only what is brighter than the threshold, with a soft knee
+@fragment
+fn fs_bright(in: VertexOutput) -> @location(0) vec4<f32> {
+  let c = textureSampleLevel(source_tex, post_sampler, in.uv, 0.0).rgb;
+  let l = luminance(c);
+  let knee = pp.bloom_threshold * 0.5;
+  let soft = clamp(l - pp.bloom_threshold + knee, 0.0, 2.0 * knee);
+  let contribution = max(soft * soft / (4.0 * knee + 0.00001), l - pp.bloom_threshold);
+  return vec4(c * contribution / max(l, 0.00001), 1.0);
+}
+
+fn blur(uv: vec2<f32>, dir: vec2<f32>) -> vec4<f32> {
+  // Constant arrays can only be indexed dynamically through a local copy
+  var offsets = BLUR_OFFSETS;
+  var weights = BLUR_WEIGHTS;
+  let texel = dir / vec2<f32>(textureDimensions(source_tex));
+  var c = textureSampleLevel(source_tex, post_sampler, uv, 0.0).rgb * weights[0];
+
+  for (var i: i32 = 1; i < 3; i++) {
+    let offset = texel * offsets[i];
+    c += textureSampleLevel(source_tex, post_sampler, uv + offset, 0.0).rgb * weights[i];
+    c += textureSampleLevel(source_tex, post_sampler, uv - offset, 0.0).rgb * weights[i];
+  }
+
+  return vec4(c, 1.0);
+}
+
+@fragment
+fn fs_blur_h(in: VertexOutput) -> @location(0) vec4<f32> {
+  return blur(in.uv, vec2(1.0, 0.0));
+}
+
+@fragment
+fn fs_blur_v(in: VertexOutput) -> @location(0) vec4<f32> {
+  return blur(in.uv, vec2(0.0, 1.0));
+}
+
+// TONEMAPPING
+// Narkowicz's fit of the ACES filmic curve
+fn tonemap_aces(c: vec3<f32>) -> vec3<f32> {
+  return clamp((c * (2.51 * c + 0.03)) / (c * (2.43 * c + 0.59) + 0.14), vec3(0.0), vec3(1.0));
+}
+
+fn tonemap_reinhard(c: vec3<f32>) -> vec3<f32> {
+  return c / (1.0 + luminance(c));
+}
+
+fn agx_contrast(x: vec3<f32>) -> vec3<f32> {
+  let x2 = x * x;
+  let x4 = x2 * x2;
+  return 15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2
+    + 0.1191 * x - 0.00232;
+}
+
+fn tonemap_agx(c: vec3<f32>) -> vec3<f32> {
+  var v = AGX_MAT * c;
+  v = clamp(log2(max(v, vec3(0.0000001))), vec3(AGX_MIN_EV), vec3(AGX_MAX_EV));
+  v = (v - AGX_MIN_EV) / (AGX_MAX_EV - AGX_MIN_EV);
+  v = agx_contrast(v);
+  // The curve outputs display encoded values, back to linear for the sRGB pass
+  return pow(max(AGX_MAT_INV * v, vec3(0.0)), vec3(2.2));
+}
+
+fn tonemap(c: vec3<f32>) -> vec3<f32> {
+  switch u32(pp.tonemapper) {
+    case TONEMAP_ACES: {
+      return tonemap_aces(c);
+    }
+    case TONEMAP_REINHARD: {
+      return tonemap_reinhard(c);
+    }
+    case TONEMAP_AGX: {
+      return tonemap_agx(c);
+    }
+    default: {
+      return c;
+    }
+  }
+}
+
+fn linear_to_srgb(c: vec3<f32>) -> vec3<f32> {
+  let x = clamp(c, vec3(0.0), vec3(1.0));
+  let lo = x * 12.92;
+  let hi = 1.055 * pow(x, vec3(1.0 / 2.4)) - 0.055;
+  return select(hi, lo, x <= vec3(0.0031308));
+}
+
+// OUTPUT
+@fragment
+fn fs_composite(in: VertexOutput) -> @location(0) vec4<f32> {
+  var c = textureSampleLevel(source_tex, post_sampler, in.uv, 0.0).rgb;
+
+  if (pp.bloom_enabled > 0.5) {
+    c += textureSampleLevel(bloom_tex, post_sampler, in.uv, 0.0).rgb * pp.bloom_intensity;
+  }
+
+  if (pp.exposure_enabled > 0.5) {
+    c *= exp2(pp.exposure);
+  }
+
+  c = tonemap(c);
+
+  // The swapchain is viewed without its sRGB suffix so the encoding can be toggled
+  if (pp.srgb_enabled > 0.5) {
+    c = linear_to_srgb(c);
+  }
+
+  return vec4(c, 1.0);
+}
@@ -1,19 +1,45 @@
 use super::control_state::{update_controls, KeyboardState};
+use super::startup_state::{StartupEvent, StartupPhase, StartupState, TerrainAnalysis};
 use crate::{
+    analysis::{
+        coast_field::{coast_wave_directions, jump_flood_steps},
+        height_field::HeightField,
+        terrain_report::TerrainReport,
+    },
+    camera::{
+        camera_controller::Camera,
+        camera_path::{apply_keyframe, capture_keyframe, path_duration, sample_camera_path},
+        surface_camera::update_surface_camera,
+    },
     collections::{
         consts::{
+            AA_OFF, AA_TAA, CAMERA_ORBIT, CAMERA_PATH_FRAME_TIME, CAMERA_SURFACE,
+            JUMP_FLOOD_STEP_STRIDE, MOON_TEXTURE_HEIGHT, MOON_TEXTURE_WIDTH, MOON_TEX_BUF_SIZE,
             MOON_TEX_DISPATCH_SIZE_X, MOON_TEX_DISPATCH_SIZE_Y, PLANET_TEXTURE_HEIGHT,
-            PLANET_TEXTURE_WIDTH, PLANET_TEX_DISPATCH_SIZE_X, PLANET_TEX_DISPATCH_SIZE_Y,
+            PLANET_TEXTURE_WIDTH, PLANET_TEX_BUF_SIZE, PLANET_TEX_DISPATCH_SIZE_X,
+            PLANET_TEX_DISPATCH_SIZE_Y, RAY_MARCH_BENCHMARK_FRAMES, RAY_MARCH_BENCHMARK_TIME,
+            RAY_MARCH_BENCHMARK_VIEWS, SCREENSHOT_DIR, WAVE_DISPATCH_SIZE_X, WAVE_DISPATCH_SIZE_Y,
+            WINDOW_TITLE,
+        },
+        structs::{
+            BindGroups, Buffers, CameraPath, Keyframe, Params, Pipelines, PlanetTexture,
+            ShaderModules, ViewParams,
         },
-        structs::{BindGroups, Buffers, Params, Pipelines, PlanetTexture, Point},
         vertices::VERTICES,
     },
+    init::env_map::load_env_map,
     init::init_functions::{
         init_bind_groups, init_buffers, init_params, init_pipelines, init_shader_modules,
         init_textures,
     },
+    init::param_files::{load_camera_path, load_params},
+    post::post_process::{taa_jitter, PostProcess},
+    readback::readback_queue::ReadbackQueue,
+    reload::shader_reload::{reload_changed_shaders, ShaderWatcher},
     updates::param_updates::{
-        update_cpu_read_buffers, update_debug_params_buffer, update_view_params_buffer,
+        update_aa_params_buffer, update_camera_params_buffer, update_cloud_params_buffer,
+        update_debug_params_buffer, update_light_params_buffer, update_post_params_buffer,
+        update_ray_params_buffer, update_terrain_params_buffer,
     },
 };
 use std::sync::Arc;
@@ -30,8 +56,20 @@ pub(crate) struct State<'a> {
     pub(crate) buffers: Buffers,
     pub(crate) bind_groups: BindGroups,
     pub(crate) pipelines: Pipelines,
+    // Kept so a reloaded shader can be paired with the unchanged ones
+    pub(crate) shader_modules: ShaderModules,
+    pub(crate) shader_watcher: ShaderWatcher,
     pub(crate) controls: KeyboardState,
     pub(crate) planet_texture: PlanetTexture,
+    pub(crate) readback: ReadbackQueue,
+    pub(crate) post: PostProcess,
+    pub(crate) startup: StartupState,
+    // None until the startup analysis has finished
+    pub(crate) terrain_analysis: Option<TerrainAnalysis>,
+    pub(crate) pending_report_export: bool,
+    pub(crate) camera_path: CameraPath,
+    // Frame of the camera path on screen, None when not playing
+    pub(crate) path_frame: Option<u32>,
     pub(crate) app_time: std::time::Instant,
     // Keep window at the bottom,
     // must be dropped after surface
@@ -39,7 +77,11 @@ pub(crate) struct State<'a> {
 }
 
 impl<'a> State<'a> {
-    pub(crate) async fn new(window: Arc<winit::window::Window>) -> Self {
+    pub(crate) async fn new(
+        window: Arc<winit::window::Window>,
+        params_path: Option<std::path::PathBuf>,
+        env_map_path: Option<std::path::PathBuf>,
+    ) -> Self {
         let size = window.inner_size();
 
         let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
@@ -93,22 +135,44 @@ impl<'a> State<'a> {
             height: size.height,
             present_mode: surface_caps.present_modes[0],
             desired_maximum_frame_latency: 1,
-            view_formats: vec![wgpu::TextureFormat::Bgra8UnormSrgb],
+            // The post process writes through a linear view and encodes sRGB itself
+            view_formats: vec![surface_format.remove_srgb_suffix()],
             alpha_mode: surface_caps.alpha_modes[0],
         };
 
         surface.configure(&device, &surface_config);
 
         let shader_modules = init_shader_modules(&device);
-        let params = init_params();
+        let mut params = match params_path {
+            Some(path) => load_params(&path).unwrap_or_else(|e| {
+                log::error!("Could not load {}, using defaults: {:?}", path.display(), e);
+                init_params()
+            }),
+            None => init_params(),
+        };
+        let env_map = env_map_path.and_then(|path| {
+            load_env_map(&path, device.limits().max_texture_dimension_2d)
+                .map_err(|e| log::error!("Could not load {}: {:?}", path.display(), e))
+                .ok()
+        });
+        params.background_params.env_map_enabled = if env_map.is_some() { 1.0 } else { 0.0 };
         let buffers = init_buffers(&device, &params);
-        let textures = init_textures(&device, &queue);
+        let textures = init_textures(&device, &queue, env_map.as_ref());
         let bind_groups = init_bind_groups(&device, &buffers, &textures);
         let pipelines = init_pipelines(&device, &bind_groups, &shader_modules);
+        let post = PostProcess::new(
+            &device,
+            &shader_modules.post,
+            &buffers,
+            surface_format.remove_srgb_suffix(),
+            size.width,
+            size.height,
+        );
         let controls = KeyboardState::new();
         let planet_texture = PlanetTexture {
             planet_tex: textures.planet_tex,
             planet_tex_extent: textures.planet_tex_extent,
+            moon_tex: textures.moon_tex,
         };
 
         Self {
@@ -118,11 +182,20 @@ impl<'a> State<'a> {
             surface_config,
             size,
             pipelines,
+            shader_modules,
+            shader_watcher: ShaderWatcher::new(),
+            post,
             params,
             buffers,
             bind_groups,
             controls,
             planet_texture,
+            readback: ReadbackQueue::new(),
+            startup: StartupState::new(),
+            terrain_analysis: None,
+            pending_report_export: false,
+            camera_path: CameraPath::default(),
+            path_frame: None,
             app_time,
             // Keep at bottom, must be dropped after surface
             // and declared after it
@@ -132,16 +205,22 @@ impl<'a> State<'a> {
 
     pub(crate) fn update(&mut self) {
         update_controls(self);
+        reload_changed_shaders(self);
+        self.update_camera_path();
         update_debug_params_buffer(self);
-        update_view_params_buffer(self);
-        update_cpu_read_buffers(self);
+        self.update_surface_camera();
+        update_camera_params_buffer(self);
+        self.update_anti_aliasing();
+        self.readback.poll(&self.device);
+        self.update_startup();
     }
 
     pub(crate) fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
         let output = self.surface.get_current_texture()?;
-        let view = output
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
+        let view = output.texture.create_view(&wgpu::TextureViewDescriptor {
+            format: Some(self.surface_config.format.remove_srgb_suffix()),
+            ..Default::default()
+        });
 
         let mut encoder = self
             .device
@@ -149,36 +228,55 @@ impl<'a> State<'a> {
                 label: Some("Render Encoder"),
             });
 
-        {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Render Pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
+        self.encode_scene_pass(&mut encoder);
+
+        let bloom_enabled = self.params.post_params.bloom_enabled > 0.5;
+        let taa_enabled = self.params.aa_params.mode == AA_TAA;
+        self.post
+            .run(&mut encoder, &view, bloom_enabled, taa_enabled);
+
+        self.queue.submit(Some(encoder.finish()));
+        output.present();
+        self.advance_camera_path();
+
+        Ok(())
+    }
+
+    // Ray marches the scene into the HDR and motion targets
+    fn encode_scene_pass(&self, encoder: &mut wgpu::CommandEncoder) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Render Pass"),
+            color_attachments: &[
+                Some(wgpu::RenderPassColorAttachment {
+                    view: self.post.hdr_view(),
                     resolve_target: None,
                     ops: wgpu::Operations {
                         load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
                         store: wgpu::StoreOp::Store,
                     },
-                })],
-                ..Default::default()
-            });
-
-            render_pass.set_pipeline(&self.pipelines.render);
-
-            render_pass.set_bind_group(0, &self.bind_groups.uniform_bg, &[]);
-            render_pass.set_bind_group(1, &self.bind_groups.frag_bg, &[]);
-            render_pass.set_bind_group(2, &self.bind_groups.sampled_texture_bg, &[]);
-            render_pass.set_vertex_buffer(0, self.buffers.vertex.slice(..));
+                }),
+                Some(wgpu::RenderPassColorAttachment {
+                    view: self.post.motion_view(),
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: wgpu::StoreOp::Store,
+                    },
+                }),
+            ],
+            ..Default::default()
+        });
 
-            let vertex_range = 0..VERTICES.len() as u32;
-            let instance_range = 0..1;
-            render_pass.draw(vertex_range, instance_range);
-        }
+        render_pass.set_pipeline(&self.pipelines.render);
 
-        self.queue.submit(Some(encoder.finish()));
-        output.present();
+        render_pass.set_bind_group(0, &self.bind_groups.uniform_bg, &[]);
+        render_pass.set_bind_group(1, &self.bind_groups.frag_bg, &[]);
+        render_pass.set_bind_group(2, &self.bind_groups.sampled_texture_bg, &[]);
+        render_pass.set_vertex_buffer(0, self.buffers.vertex.slice(..));
 
-        Ok(())
+        let vertex_range = 0..VERTICES.len() as u32;
+        let instance_range = 0..1;
+        render_pass.draw(vertex_range, instance_range);
     }
 
     pub(crate) fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
@@ -187,13 +285,267 @@ impl<'a> State<'a> {
             self.surface_config.width = new_size.width;
             self.surface_config.height = new_size.height;
             self.surface.configure(&self.device, &self.surface_config);
+            self.post
+                .resize(&self.device, &self.buffers, new_size.width, new_size.height);
+            // The history no longer lines up with the screen
+            self.params.aa_params.frame_index = 0.0;
+        }
+    }
+
+    // Follows the ground, which turns with the planet even when standing still
+    fn update_surface_camera(&mut self) {
+        if self.params.view_params.controller != CAMERA_SURFACE {
+            return;
+        }
+
+        let time = self.get_time();
+        if let Some(analysis) = &self.terrain_analysis {
+            update_surface_camera(&mut self.params.view_params, &analysis.height_field, time);
+        }
+    }
+
+    // Jitters TAA frames and keeps the previous view for reprojection
+    fn update_anti_aliasing(&mut self) {
+        let time = self.get_time();
+        let camera = Camera::from_view(&self.params.view_params).params(&self.params.view_params);
+        let aa = &mut self.params.aa_params;
+
+        if aa.mode == AA_TAA {
+            let frame = aa.frame_index as u32;
+            let [jitter_x, jitter_y] = taa_jitter(frame);
+            aa.jitter_x = jitter_x;
+            aa.jitter_y = jitter_y;
+            aa.history_valid = if frame > 0 { 1.0 } else { 0.0 };
+            aa.frame_index = (frame % 1024 + 1) as f32;
+        } else {
+            aa.jitter_x = 0.0;
+            aa.jitter_y = 0.0;
+            aa.history_valid = 0.0;
+            aa.frame_index = 0.0;
         }
+
+        update_aa_params_buffer(self);
+
+        // The buffer already holds this frame's values, the next frame reprojects onto them
+        let aa = &mut self.params.aa_params;
+        aa.prev_time = time;
+        aa.prev_position_x = camera.position_x;
+        aa.prev_position_y = camera.position_y;
+        aa.prev_position_z = camera.position_z;
+        aa.prev_fov_y = camera.fov_y;
+        aa.prev_rotation_x = camera.rotation_x;
+        aa.prev_rotation_y = camera.rotation_y;
+        aa.prev_rotation_z = camera.rotation_z;
+        aa.prev_rotation_w = camera.rotation_w;
     }
 
     pub(crate) fn get_time(&self) -> f32 {
+        // Camera path playback drives the clock
+        if let Some(keyframe) = self.path_keyframe() {
+            return keyframe.scene_time;
+        }
+
         self.app_time.elapsed().as_secs_f32()
     }
 
+    fn path_keyframe(&self) -> Option<Keyframe> {
+        let frame = self.path_frame?;
+        sample_camera_path(&self.camera_path, frame as f32 * CAMERA_PATH_FRAME_TIME)
+    }
+
+    pub(crate) fn add_keyframe(&mut self) {
+        let keyframe = capture_keyframe(&self.camera_path, &self.params, self.get_time());
+        self.camera_path.seed = self.params.terrain_params.seed;
+        self.camera_path.keyframes.push(keyframe);
+        println!(
+            "Keyframe {} at {:.1}s (scene time {:.1}s)",
+            self.camera_path.keyframes.len(),
+            keyframe.path_time,
+            keyframe.scene_time
+        );
+    }
+
+    pub(crate) fn remove_keyframe(&mut self) {
+        self.camera_path.keyframes.pop();
+        println!("{} keyframes", self.camera_path.keyframes.len());
+    }
+
+    pub(crate) fn play_camera_path(&mut self) {
+        if self.camera_path.keyframes.is_empty() {
+            println!("Camera path has no keyframes");
+            return;
+        }
+
+        println!(
+            "Playing camera path ({:.1}s)",
+            path_duration(&self.camera_path)
+        );
+        self.path_frame = Some(0);
+    }
+
+    pub(crate) fn stop_camera_path(&mut self) {
+        self.path_frame = None;
+    }
+
+    // Returns true when the path was recorded on a different terrain seed,
+    // the seed is switched over but the terrain is left to the caller
+    pub(crate) fn load_camera_path(&mut self, file: &std::path::Path) -> std::io::Result<bool> {
+        self.camera_path = load_camera_path(file)?;
+        self.path_frame = None;
+
+        if self.camera_path.seed == self.params.terrain_params.seed {
+            return Ok(false);
+        }
+
+        self.params.terrain_params.seed = self.camera_path.seed;
+        update_terrain_params_buffer(self);
+        Ok(true)
+    }
+
+    fn update_camera_path(&mut self) {
+        if self.path_frame.is_none() {
+            return;
+        }
+
+        let Some(keyframe) = self.path_keyframe() else {
+            self.stop_camera_path();
+            return;
+        };
+
+        if keyframe.path_time > path_duration(&self.camera_path) {
+            println!("Camera path finished");
+            self.stop_camera_path();
+            return;
+        }
+
+        apply_keyframe(&mut self.params, &keyframe);
+        update_post_params_buffer(self);
+        update_light_params_buffer(self);
+        update_cloud_params_buffer(self);
+        self.queue.write_buffer(
+            &self.buffers.time_uniform,
+            0,
+            bytemuck::cast_slice(&[keyframe.scene_time]),
+        );
+    }
+
+    fn advance_camera_path(&mut self) {
+        if let Some(frame) = &mut self.path_frame {
+            *frame += 1;
+        }
+    }
+
+    // Generation adds onto the terrain textures, so both start again from zero
+    pub(crate) fn regenerate_terrain(&mut self) {
+        let planet = vec![0u8; PLANET_TEX_BUF_SIZE];
+        let moon = vec![0u8; MOON_TEX_BUF_SIZE];
+
+        for (texture, data, width, height) in [
+            (
+                &self.planet_texture.planet_tex,
+                &planet,
+                PLANET_TEXTURE_WIDTH,
+                PLANET_TEXTURE_HEIGHT,
+            ),
+            (
+                &self.planet_texture.moon_tex,
+                &moon,
+                MOON_TEXTURE_WIDTH,
+                MOON_TEXTURE_HEIGHT,
+            ),
+        ] {
+            self.queue.write_texture(
+                texture.as_image_copy(),
+                data,
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(width * 4 * 4),
+                    rows_per_image: Some(height),
+                },
+                wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+
+        self.terrain_analysis = None;
+        self.start_terrain_generation();
+    }
+
+    // Queues terrain generation and returns straight away, the analysis
+    // finishes in the background while frames keep rendering
+    pub(crate) fn start_terrain_generation(&mut self) {
+        self.startup.restart();
+        self.update_loading_indicator();
+
+        self.init_planet_terrain();
+        self.init_moon_terrain();
+        self.calculate_wave_dir();
+
+        let tx = self.startup.sender();
+        self.queue.on_submitted_work_done(move || {
+            let _ = tx.send(StartupEvent::GpuWorkDone);
+        });
+
+        let height_map = self.readback.read_texture::<f32>(
+            &self.device,
+            &self.queue,
+            &self.planet_texture.planet_tex,
+            "Planet heightmap",
+        );
+
+        let tx = self.startup.sender();
+        self.readback
+            .on_complete(height_map, move |result| match result {
+                Ok(map) => {
+                    let _ = tx.send(StartupEvent::HeightMapRead);
+
+                    std::thread::spawn(move || {
+                        let analysis = TerrainAnalysis {
+                            report: TerrainReport::from_height_map(&map),
+                            height_field: HeightField::from_height_map(&map),
+                        };
+                        let _ = tx.send(StartupEvent::AnalysisDone(Box::new(analysis)));
+                    });
+                }
+                Err(e) => {
+                    let _ = tx.send(StartupEvent::HeightMapFailed(e));
+                }
+            });
+    }
+
+    fn update_startup(&mut self) {
+        if self.startup.phase() == StartupPhase::Done {
+            return;
+        }
+
+        let phase = self.startup.phase();
+
+        if let Some(analysis) = self.startup.poll() {
+            self.terrain_analysis = Some(analysis);
+
+            if self.pending_report_export {
+                self.pending_report_export = false;
+                self.export_terrain_report();
+            }
+        }
+
+        if phase != self.startup.phase() {
+            self.update_loading_indicator();
+        }
+    }
+
+    fn update_loading_indicator(&self) {
+        match self.startup.phase() {
+            StartupPhase::Done => self.window.set_title(WINDOW_TITLE),
+            phase => self
+                .window
+                .set_title(&format!("{} - {}...", WINDOW_TITLE, phase.label())),
+        }
+    }
+
     pub(crate) fn init_planet_terrain(&mut self) {
         let mut encoder = self
             .device
@@ -241,38 +593,6 @@ impl<'a> State<'a> {
         self.queue.submit(Some(encoder.finish()));
     }
 
-    fn copy_tex_to_buffer(&mut self) {
-        let mut encoder = self
-            .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                label: Some("Generate moon terrain tex -> buf - encoder"),
-            });
-
-        encoder.copy_texture_to_buffer(
-            wgpu::ImageCopyTexture {
-                texture: &self.planet_texture.planet_tex,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-                aspect: wgpu::TextureAspect::All,
-            },
-            wgpu::ImageCopyBuffer {
-                buffer: &self.buffers.planet_tex_buffer,
-                layout: wgpu::ImageDataLayout {
-                    offset: 0,
-                    bytes_per_row: Some(PLANET_TEXTURE_WIDTH * 4 * 4), // 16bytes -> 4*f32
-                    rows_per_image: Some(PLANET_TEXTURE_HEIGHT),
-                },
-            },
-            wgpu::Extent3d {
-                width: PLANET_TEXTURE_WIDTH,
-                height: PLANET_TEXTURE_HEIGHT,
-                depth_or_array_layers: 1,
-            },
-        );
-
-        self.queue.submit(Some(encoder.finish()));
-    }
-
     fn copy_buffer_to_tex(&mut self, map: Vec<f32>) {
         let map_slice = bytemuck::cast_slice(&map);
 
@@ -315,133 +635,182 @@ impl<'a> State<'a> {
         self.queue.submit(Some(encoder.finish()));
     }
 
-    fn copy_buffer_data(&mut self) -> Result<Vec<f32>, futures::channel::oneshot::Canceled> {
-        let buffer_slice = self.buffers.planet_tex_buffer.slice(..);
-        let (tx, rx) = futures::channel::oneshot::channel();
-
-        buffer_slice.map_async(wgpu::MapMode::Read, move |result| {
-            tx.send(result).unwrap();
-        });
+    pub(crate) fn calculate_wave_dir(&mut self) {
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Wave directions - encoder"),
+            });
 
-        self.device.poll(wgpu::Maintain::Wait);
-        let result = futures::executor::block_on(rx);
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Wave directions - compute pass"),
+                timestamp_writes: None,
+            });
+            compute_pass.set_bind_group(0, &self.bind_groups.uniform_bg, &[]);
+            compute_pass.set_bind_group(1, &self.bind_groups.compute_bg, &[]);
+            compute_pass.set_bind_group(2, &self.bind_groups.texture_bg, &[]);
 
-        match result {
-            Ok(_) => {
-                let buf_view = buffer_slice.get_mapped_range();
-                let data: &[u8] = bytemuck::cast_slice(&buf_view);
-                let data_f32: &[[f32; 4]] = bytemuck::cast_slice(data);
-                let mut flattened_data = Vec::new();
+            // Seeds are written to a, then ping-pong between the buffers
+            let ping_pong = [&self.bind_groups.wave_bg_ab, &self.bind_groups.wave_bg_ba];
+            let mut current = 0;
 
-                for i in data_f32.into_iter() {
-                    flattened_data.extend(i.to_owned());
-                }
+            compute_pass.set_pipeline(&self.pipelines.init_coast_seeds);
+            compute_pass.set_bind_group(3, ping_pong[1], &[0]);
+            compute_pass.dispatch_workgroups(WAVE_DISPATCH_SIZE_X, WAVE_DISPATCH_SIZE_Y, 1);
 
-                return Ok(flattened_data);
+            compute_pass.set_pipeline(&self.pipelines.jump_flood);
+            for i in 0..jump_flood_steps().len() as u64 {
+                let offset = (i * JUMP_FLOOD_STEP_STRIDE) as u32;
+                compute_pass.set_bind_group(3, ping_pong[current], &[offset]);
+                compute_pass.dispatch_workgroups(WAVE_DISPATCH_SIZE_X, WAVE_DISPATCH_SIZE_Y, 1);
+                current = 1 - current;
             }
-            Err(e) => Err(e),
+
+            compute_pass.set_pipeline(&self.pipelines.write_wave_directions);
+            compute_pass.set_bind_group(3, ping_pong[current], &[0]);
+            compute_pass.dispatch_workgroups(WAVE_DISPATCH_SIZE_X, WAVE_DISPATCH_SIZE_Y, 1);
         }
+
+        self.queue.submit(Some(encoder.finish()));
     }
 
-    fn find_extreme_elevations(&self, map: &Vec<Point>, count: usize) -> Vec<Point> {
-        let mut min_vals = Vec::with_capacity(count);
+    pub(crate) fn benchmark_wave_dir(&mut self) {
+        // Start both runs from an idle queue
+        self.device.poll(wgpu::Maintain::Wait);
+
+        let cpu_start = std::time::Instant::now();
+        self.calculate_wave_dir_cpu();
+        self.device.poll(wgpu::Maintain::Wait);
+        let cpu_time = cpu_start.elapsed();
 
-        // Avoid returning 16 points all clustered together on the map
-        let exclusion_zone = 512;
+        let gpu_start = std::time::Instant::now();
+        self.calculate_wave_dir();
+        self.device.poll(wgpu::Maintain::Wait);
+        let gpu_time = gpu_start.elapsed();
+
+        println!("\n------------------------------------------------------");
+        println!("Wave direction analysis (jump flood distance to coast)");
+        println!("CPU: {:>10.2} ms", cpu_time.as_secs_f64() * 1000.0);
+        println!("GPU: {:>10.2} ms", gpu_time.as_secs_f64() * 1000.0);
+        println!(
+            "Speedup: {:.1}x",
+            cpu_time.as_secs_f64() / gpu_time.as_secs_f64().max(f64::EPSILON)
+        );
+        println!("------------------------------------------------------\n");
+    }
 
-        for _ in 0..count {
-            let mut current_min = Point {
-                elevation: std::f32::MAX,
-                x: std::u32::MAX,
-                y: std::u32::MAX,
-            };
+    // Renders each benchmark view with the plain and the accelerated ray march,
+    // one frame at a time so each measurement covers only its own GPU work
+    pub(crate) fn benchmark_ray_march(&mut self) {
+        let saved_view = self.params.view_params;
+        let saved_ray = self.params.ray_params;
+        let saved_aa = self.params.aa_params;
+
+        // Supersampling would multiply both runs, leave it out of the numbers
+        self.params.aa_params.mode = AA_OFF;
+        update_aa_params_buffer(self);
+        self.queue.write_buffer(
+            &self.buffers.time_uniform,
+            0,
+            bytemuck::cast_slice(&[RAY_MARCH_BENCHMARK_TIME]),
+        );
 
-            for j in 0..map.len() {
-                let point = map[j];
+        println!("\n------------------------------------------------------");
+        println!("Ray march benchmark ({RAY_MARCH_BENCHMARK_FRAMES} frames per view)");
+        println!(
+            "{:<12} {:>12} {:>12} {:>9}",
+            "view", "plain ms", "bounded ms", "speedup"
+        );
 
-                // Skip this point if it's too close to any point in min_vals
-                if min_vals
-                    .iter()
-                    .any(|min_point| point.manhattan_distance(min_point) <= exclusion_zone)
-                {
-                    continue;
-                }
+        let mut total_plain = 0.0;
+        let mut total_bounded = 0.0;
 
-                if point.elevation < current_min.elevation {
-                    current_min = point;
-                }
+        for (name, orbit_yaw, orbit_pitch, orbit_distance) in RAY_MARCH_BENCHMARK_VIEWS {
+            self.params.view_params = ViewParams {
+                controller: CAMERA_ORBIT,
+                orbit_yaw,
+                orbit_pitch,
+                orbit_distance,
+                ..saved_view
+            };
+            update_camera_params_buffer(self);
+
+            let mut times = [0.0; 2];
+            for (accelerate, time) in [0.0, 1.0].into_iter().zip(times.iter_mut()) {
+                self.params.ray_params.accelerate = accelerate;
+                update_ray_params_buffer(self);
+                *time = self.time_scene_frames(RAY_MARCH_BENCHMARK_FRAMES);
             }
 
-            min_vals.push(current_min);
+            let [plain, bounded] = times;
+            total_plain += plain;
+            total_bounded += bounded;
+            println!(
+                "{:<12} {:>12.2} {:>12.2} {:>8.1}x",
+                name,
+                plain,
+                bounded,
+                plain / bounded.max(f64::EPSILON)
+            );
         }
 
-        return min_vals;
+        println!(
+            "{:<12} {:>12.2} {:>12.2} {:>8.1}x",
+            "total",
+            total_plain,
+            total_bounded,
+            total_plain / total_bounded.max(f64::EPSILON)
+        );
+        println!("------------------------------------------------------\n");
+
+        self.params.view_params = saved_view;
+        self.params.ray_params = saved_ray;
+        self.params.aa_params = saved_aa;
+        update_camera_params_buffer(self);
+        update_ray_params_buffer(self);
+        update_aa_params_buffer(self);
+        self.queue.write_buffer(
+            &self.buffers.time_uniform,
+            0,
+            bytemuck::cast_slice(&[self.get_time()]),
+        );
     }
 
-    fn get_wave_directions(&mut self, map: &Vec<Point>, min_vals: Vec<Point>) -> Vec<(f32, f32)> {
-        let mut wave_dirs = Vec::with_capacity(map.len());
-
-        for i in 0..map.len() {
-            let point = map[i];
-
-            // Find the nearest minimum elevation to point
-            let nearest_low_point: Point = min_vals.iter().fold(
-                Point {
-                    elevation: std::f32::MAX,
-                    x: 20000u32,
-                    y: 20000u32,
-                },
-                |mut current_nearest, val| {
-                    if current_nearest.manhattan_distance(&point) > val.manhattan_distance(&point) {
-                        current_nearest = *val;
-                    }
-
-                    return current_nearest;
-                },
-            );
-
-            // Waves should move away from the deeper point to the shallower areas
-            let dirx = point.x as f32 - nearest_low_point.x as f32;
-            let diry = point.y as f32 - nearest_low_point.y as f32;
-            let wdir = nalgebra::Vector2::new(dirx, diry);
-            let normalized = nalgebra::Unit::new_normalize(wdir);
-            wave_dirs.push((normalized.x, normalized.y));
+    // Average milliseconds per scene pass
+    fn time_scene_frames(&mut self, frames: u32) -> f64 {
+        // Start from an idle queue
+        self.device.poll(wgpu::Maintain::Wait);
+        let start = std::time::Instant::now();
+
+        for _ in 0..frames {
+            let mut encoder = self
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Benchmark Encoder"),
+                });
+            self.encode_scene_pass(&mut encoder);
+            self.queue.submit(Some(encoder.finish()));
+            self.device.poll(wgpu::Maintain::Wait);
         }
 
-        return wave_dirs;
+        start.elapsed().as_secs_f64() * 1000.0 / frames as f64
     }
 
-    pub(crate) fn calculate_wave_dir(&mut self) {
-        self.copy_tex_to_buffer();
-        let height_map = self.copy_buffer_data();
+    // Reference implementation, kept for benchmarking against the GPU version
+    fn calculate_wave_dir_cpu(&mut self) {
+        let height_map = self.readback.read_texture::<f32>(
+            &self.device,
+            &self.queue,
+            &self.planet_texture.planet_tex,
+            "Planet heightmap",
+        );
 
-        match height_map {
+        // The CPU path needs the heightmap before it can do anything,
+        // blocking here only stalls the benchmark
+        match self.readback.wait(&self.device, height_map) {
             Ok(mut map) => {
-                let mut y = 0u32;
-
-                let indexed_map = map
-                    .iter()
-                    .enumerate()
-                    .filter_map(|(idx, val)| if idx % 4 == 0 { Some(val) } else { None })
-                    .enumerate()
-                    .map(|(idx, val)| {
-                        let x = idx as u32 % PLANET_TEXTURE_WIDTH;
-
-                        if x == 0 && idx != 0 {
-                            y += 1;
-                        }
-
-                        return Point {
-                            elevation: *val,
-                            x,
-                            y,
-                        };
-                    })
-                    .collect::<Vec<Point>>();
-
-                let min_values = self.find_extreme_elevations(&indexed_map, 16);
-
-                let wave_dirs = self.get_wave_directions(&indexed_map, min_values);
+                let wave_dirs = coast_wave_directions(&map);
 
                 // Double-check that the map and wave_dirs are compatible
                 assert_eq!(
@@ -460,4 +829,26 @@ impl<'a> State<'a> {
             Err(e) => eprintln!("Error mapping planet texture buffer: {:?}", e),
         }
     }
+
+    pub(crate) fn export_terrain_report(&mut self) {
+        let Some(analysis) = &self.terrain_analysis else {
+            // Exported as soon as the startup analysis finishes
+            println!(
+                "Terrain report not ready yet ({})",
+                self.startup.phase().label()
+            );
+            self.pending_report_export = true;
+            return;
+        };
+
+        println!("\n{}", analysis.report);
+
+        match analysis
+            .report
+            .write_files(std::path::Path::new(SCREENSHOT_DIR))
+        {
+            Ok((json, csv)) => println!("Wrote {} and {}", json.display(), csv.display()),
+            Err(e) => eprintln!("Error writing terrain report: {:?}", e),
+        }
+    }
 }
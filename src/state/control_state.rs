@@ -1,12 +1,26 @@
 use std::collections::HashSet;
-use std::thread;
-use std::time;
+use std::path::Path;
 
 use winit::keyboard::{KeyCode, PhysicalKey};
 
 // use crate::updates::update_functions::update_terrain_buffer;
 
-use crate::updates::param_updates::update_view_params_buffer;
+use crate::analysis::eclipses::predict_eclipses;
+use crate::camera::camera_controller::{dolly_camera, move_camera, rotate_camera, set_controller};
+use crate::camera::surface_camera::enter_surface_mode;
+use crate::collections::consts::{
+    AA_OFF, AA_SSAA, AA_TAA, CAMERA_FREE_FLY, CAMERA_ORBIT, CAMERA_SURFACE, DEBUG_VIEW_HEIGHTMAP,
+    DEBUG_VIEW_MATERIAL, DEBUG_VIEW_NORMALS, DEBUG_VIEW_OFF, DEBUG_VIEW_OVERSHOOT,
+    DEBUG_VIEW_SLOPE, DEBUG_VIEW_STEPS, DEBUG_VIEW_TRIPLANAR, DEBUG_VIEW_WATER_DEPTH,
+    DEBUG_VIEW_WAVE_DIRECTION, MOON_ORBIT_SPEED, PLANET_RADIUS, SCREENSHOT_DIR,
+};
+use crate::init::param_files::{latest_camera_path, save_camera_path, save_params};
+use crate::readback::readback_queue::ReadbackQueue;
+use crate::updates::param_updates::{
+    update_atmosphere_params_buffer, update_background_params_buffer, update_cloud_params_buffer,
+    update_light_params_buffer, update_post_params_buffer, update_ray_params_buffer,
+    update_ring_params_buffer, update_water_params_buffer,
+};
 
 use super::app_state::State;
 
@@ -15,6 +29,15 @@ pub(crate) enum KeyboardMode {
     DEBUG,
     VIEW,
     TERRAIN,
+    WATER,
+    ATMOSPHERE,
+    CLOUDS,
+    RINGS,
+    LIGHT,
+    BACKGROUND,
+    POST,
+    ANTIALIAS,
+    PATH,
     PRINT,
 }
 
@@ -63,115 +86,61 @@ impl KeyboardState {
 }
 
 pub(crate) fn print_gpu_data<T: bytemuck::Pod + std::fmt::Debug>(
+    readback: &mut ReadbackQueue,
     device: &wgpu::Device,
+    queue: &wgpu::Queue,
     buffer: &wgpu::Buffer,
     obj_label: &str,
 ) {
-    // Map the buffer for reading
-    let buffer_slice = buffer.slice(..);
-    let (tx, rx) = futures::channel::oneshot::channel();
-
-    buffer_slice.map_async(wgpu::MapMode::Read, move |result| {
-        tx.send(result).unwrap();
-    });
-
     println!("buffer size: {:?}", buffer.size());
-    // Wait for the GPU to finish executing the commands
-    device.poll(wgpu::Maintain::Wait);
-    // Wait for the buffer to be mapped
-    let result = futures::executor::block_on(rx);
 
-    match result {
-        Ok(_) => {
-            let buf_view = buffer_slice.get_mapped_range();
-            let data: &[T] = bytemuck::cast_slice(&buf_view);
+    let obj_label = obj_label.to_owned();
+    let data = readback.read_buffer::<T>(device, queue, buffer, &obj_label);
 
+    readback.on_complete(data, move |result| match result {
+        Ok(data) => {
             // Print the boids current properties
             for (i, obj) in data.iter().enumerate() {
                 println!("{} {}:\n{:?}", obj_label, i, obj);
             }
-
-            drop(buf_view);
-            buffer.unmap();
         }
         Err(e) => eprintln!("Error retrieving gpu data: {:?}", e),
-    }
+    });
 }
 
 pub(crate) fn print_gpu_interleave_two_buffers<
     T: bytemuck::Pod + std::fmt::Debug + std::iter::IntoIterator,
 >(
+    readback: &mut ReadbackQueue,
     device: &wgpu::Device,
+    queue: &wgpu::Queue,
     buffer1: &wgpu::Buffer,
     buffer2: &wgpu::Buffer,
 ) where
     <T as IntoIterator>::Item: std::fmt::Debug,
 {
-    // Map the buffer for reading
-    let buffer_slice1 = buffer1.slice(..);
-    let (tx1, rx1) = futures::channel::oneshot::channel();
-
-    buffer_slice1.map_async(wgpu::MapMode::Read, move |result| {
-        tx1.send(result).unwrap();
-    });
-
-    // Wait for the GPU to finish executing the commands
-    device.poll(wgpu::Maintain::Wait);
-    // Wait for the buffer to be mapped
-    let result1 = futures::executor::block_on(rx1);
-
-    // Map the buffer for reading
-    let buffer_slice2 = buffer2.slice(..);
-    let (tx2, rx2) = futures::channel::oneshot::channel();
-
-    buffer_slice2.map_async(wgpu::MapMode::Read, move |result| {
-        tx2.send(result).unwrap();
-    });
-
-    // Wait for the GPU to finish executing the commands
-    device.poll(wgpu::Maintain::Wait);
-    // Wait for the buffer to be mapped
-    let result2 = futures::executor::block_on(rx2);
-
-    match (result1, result2) {
-        (Ok(_), Ok(_)) => {
-            let buf_view1 = buffer_slice1.get_mapped_range();
-            let data1: &[T] = bytemuck::cast_slice(&buf_view1);
-            let buf_view2 = buffer_slice2.get_mapped_range();
-            let data2: &[T] = bytemuck::cast_slice(&buf_view2);
-
-            let mut flattened_data1 = Vec::new();
-            let mut flattened_data2 = Vec::new();
-
-            for i in data1.into_iter() {
-                flattened_data1.extend(i.to_owned());
-            }
-
-            for i in data2.into_iter() {
-                flattened_data2.extend(i.to_owned());
+    let data1 = readback.read_buffer::<T>(device, queue, buffer1, "Interleave buffer1");
+    let data2 = readback.read_buffer::<T>(device, queue, buffer2, "Interleave buffer2");
+
+    readback.spawn(async move {
+        match (data1.await, data2.await) {
+            (Ok(data1), Ok(data2)) => {
+                let flattened_data1 = data1.into_iter().flatten();
+                let flattened_data2 = data2.into_iter().flatten();
+
+                for (idx, item) in flattened_data1.zip(flattened_data2).enumerate() {
+                    println!("\n{idx}:\n{:?}", item.0);
+                    println!("{:?}", item.1);
+                }
             }
-
-            for (idx, item) in flattened_data1
-                .iter()
-                .zip(flattened_data2.iter())
-                .enumerate()
-            {
-                println!("\n{idx}:\n{:?}", item.0);
-                println!("{:?}", item.1);
+            (Err(e), Ok(_)) => eprintln!("Error retrieving gpu data from buffer1: {:?}", e),
+            (Ok(_), Err(e)) => eprintln!("Error retrieving gpu data from buffer2: {:?}", e),
+            (Err(e1), Err(e2)) => {
+                eprintln!("Error retrieving gpu data from buffer1: {:?}", e1);
+                eprintln!("Error retrieving gpu data from buffer2: {:?}", e2);
             }
-
-            drop(buf_view1);
-            drop(buf_view2);
-            buffer1.unmap();
-            buffer2.unmap();
-        }
-        (Err(e), Ok(_)) => eprintln!("Error retrieving gpu data from buffer1: {:?}", e),
-        (Ok(_), Err(e)) => eprintln!("Error retrieving gpu data from buffer2: {:?}", e),
-        (Err(e1), Err(e2)) => {
-            eprintln!("Error retrieving gpu data from buffer1: {:?}", e1);
-            eprintln!("Error retrieving gpu data from buffer2: {:?}", e2);
         }
-    }
+    });
 }
 
 pub(crate) fn update_controls(state: &mut State) {
@@ -187,6 +156,51 @@ pub(crate) fn update_controls(state: &mut State) {
         .key_pressed(PhysicalKey::Code(KeyCode::Digit2))
     {
         state.controls.set_mode(KeyboardMode::VIEW);
+    } else if state
+        .controls
+        .key_pressed(PhysicalKey::Code(KeyCode::Digit3))
+    {
+        state.controls.set_mode(KeyboardMode::WATER);
+    } else if state
+        .controls
+        .key_pressed(PhysicalKey::Code(KeyCode::Digit4))
+    {
+        state.controls.set_mode(KeyboardMode::ATMOSPHERE);
+    } else if state
+        .controls
+        .key_pressed(PhysicalKey::Code(KeyCode::Digit5))
+    {
+        state.controls.set_mode(KeyboardMode::CLOUDS);
+    } else if state
+        .controls
+        .key_pressed(PhysicalKey::Code(KeyCode::Digit6))
+    {
+        state.controls.set_mode(KeyboardMode::LIGHT);
+    } else if state
+        .controls
+        .key_pressed(PhysicalKey::Code(KeyCode::Digit7))
+    {
+        state.controls.set_mode(KeyboardMode::BACKGROUND);
+    } else if state
+        .controls
+        .key_pressed(PhysicalKey::Code(KeyCode::Digit8))
+    {
+        state.controls.set_mode(KeyboardMode::POST);
+    } else if state
+        .controls
+        .key_pressed(PhysicalKey::Code(KeyCode::Digit9))
+    {
+        state.controls.set_mode(KeyboardMode::ANTIALIAS);
+    } else if state
+        .controls
+        .key_pressed(PhysicalKey::Code(KeyCode::Digit0))
+    {
+        state.controls.set_mode(KeyboardMode::PATH);
+    } else if state
+        .controls
+        .key_pressed(PhysicalKey::Code(KeyCode::Backquote))
+    {
+        state.controls.set_mode(KeyboardMode::RINGS);
     } else if state.controls.key_pressed(PhysicalKey::Code(KeyCode::KeyP)) {
         state.controls.set_mode(KeyboardMode::PRINT);
     }
@@ -195,6 +209,15 @@ pub(crate) fn update_controls(state: &mut State) {
         KeyboardMode::DEBUG => debug_controls(state),
         KeyboardMode::VIEW => view_controls(state),
         KeyboardMode::TERRAIN => terrain_controls(state),
+        KeyboardMode::WATER => water_controls(state),
+        KeyboardMode::ATMOSPHERE => atmosphere_controls(state),
+        KeyboardMode::CLOUDS => cloud_controls(state),
+        KeyboardMode::RINGS => ring_controls(state),
+        KeyboardMode::LIGHT => light_controls(state),
+        KeyboardMode::BACKGROUND => background_controls(state),
+        KeyboardMode::POST => post_controls(state),
+        KeyboardMode::ANTIALIAS => antialias_controls(state),
+        KeyboardMode::PATH => path_controls(state),
         KeyboardMode::PRINT => print_controls(state),
     }
 }
@@ -204,36 +227,79 @@ fn debug_controls(state: &mut State) {
 
     if pressed.contains(&PhysicalKey::Code(KeyCode::KeyS)) {
         print_gpu_data::<[f32; 4]>(
+            &mut state.readback,
             &state.device,
-            &state.buffers.cpu_read_generic_debug,
+            &state.queue,
+            &state.buffers.generic_debug,
             "Debug",
         );
-        thread::sleep(time::Duration::from_millis(50));
         state.controls.set_mode(KeyboardMode::TERRAIN);
     } else if pressed.contains(&PhysicalKey::Code(KeyCode::Digit1)) {
         print_gpu_data::<[[f32; 4]; 512]>(
+            &mut state.readback,
             &state.device,
-            &state.buffers.cpu_read_debug_array1,
+            &state.queue,
+            &state.buffers.debug_array1,
             "Debug",
         );
-        thread::sleep(time::Duration::from_millis(50));
         state.controls.set_mode(KeyboardMode::TERRAIN);
     } else if pressed.contains(&PhysicalKey::Code(KeyCode::Digit2)) {
         print_gpu_data::<[[f32; 4]; 512]>(
+            &mut state.readback,
             &state.device,
-            &state.buffers.cpu_read_debug_array2,
+            &state.queue,
+            &state.buffers.debug_array2,
             "Debug",
         );
-        thread::sleep(time::Duration::from_millis(50));
         state.controls.set_mode(KeyboardMode::TERRAIN);
     } else if pressed.contains(&PhysicalKey::Code(KeyCode::Digit3)) {
         print_gpu_interleave_two_buffers::<[[f32; 4]; 512]>(
+            &mut state.readback,
             &state.device,
-            &state.buffers.cpu_read_debug_array1,
-            &state.buffers.cpu_read_debug_array2,
+            &state.queue,
+            &state.buffers.debug_array1,
+            &state.buffers.debug_array2,
         );
-        thread::sleep(time::Duration::from_millis(50));
         state.controls.set_mode(KeyboardMode::TERRAIN);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyB)) {
+        state.benchmark_wave_dir();
+        state.controls.set_mode(KeyboardMode::TERRAIN);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyR)) {
+        state.benchmark_ray_march();
+        state.controls.set_mode(KeyboardMode::TERRAIN);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyO)) {
+        state.params.debug_params.view = DEBUG_VIEW_OFF;
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyN)) {
+        state.params.debug_params.view = DEBUG_VIEW_NORMALS;
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyL)) {
+        state.params.debug_params.view = DEBUG_VIEW_SLOPE;
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyT)) {
+        state.params.debug_params.view = DEBUG_VIEW_STEPS;
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyM)) {
+        state.params.debug_params.view = DEBUG_VIEW_MATERIAL;
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyW)) {
+        state.params.debug_params.view = DEBUG_VIEW_WATER_DEPTH;
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyH)) {
+        state.params.debug_params.view = DEBUG_VIEW_HEIGHTMAP;
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyE)) {
+        state.params.debug_params.view = DEBUG_VIEW_WAVE_DIRECTION;
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyG)) {
+        state.params.debug_params.view = DEBUG_VIEW_TRIPLANAR;
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyU)) {
+        state.params.debug_params.view = DEBUG_VIEW_OVERSHOOT;
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyZ)) {
+        state.params.debug_params.heightmap_channel = 0.0;
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyX)) {
+        state.params.debug_params.heightmap_channel = 1.0;
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyC)) {
+        state.params.debug_params.heightmap_channel = 2.0;
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyV)) {
+        state.params.debug_params.heightmap_channel = 3.0;
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::ArrowUp)) {
+        state.params.debug_params.step_range += 10.0;
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::ArrowDown)) {
+        state.params.debug_params.step_range =
+            f32::max(10.0, state.params.debug_params.step_range - 10.0);
     }
 }
 
@@ -260,46 +326,541 @@ fn terrain_controls(state: &mut State) {
 
 fn view_controls(state: &mut State) {
     let pressed = state.controls.get_keys();
-    let mz = state.params.view_params.zoom;
+    let shift = pressed.contains(&PhysicalKey::Code(KeyCode::ShiftLeft));
+    let time = state.get_time();
+    let vp = &mut state.params.view_params;
+    // Smaller turns when zoomed in, so the view doesn't jump across the screen
+    let turn = 0.02 * vp.fov_degrees / 20.0;
+
+    // Shift + arrows moves the free-fly and surface cameras instead of turning them
+    let strafe = shift && vp.controller != CAMERA_ORBIT;
+
+    // CAMERA -----------------------------------------------------------------------
+    if strafe && pressed.contains(&PhysicalKey::Code(KeyCode::ArrowLeft)) {
+        move_camera(vp, -1.0, 0.0);
+    } else if strafe && pressed.contains(&PhysicalKey::Code(KeyCode::ArrowRight)) {
+        move_camera(vp, 1.0, 0.0);
+    } else if strafe && pressed.contains(&PhysicalKey::Code(KeyCode::ArrowUp)) {
+        move_camera(vp, 0.0, 1.0);
+    } else if strafe && pressed.contains(&PhysicalKey::Code(KeyCode::ArrowDown)) {
+        move_camera(vp, 0.0, -1.0);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::ArrowLeft)) {
+        rotate_camera(vp, -turn, 0.0);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::ArrowRight)) {
+        rotate_camera(vp, turn, 0.0);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::ArrowUp)) {
+        rotate_camera(vp, 0.0, turn);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::ArrowDown)) {
+        rotate_camera(vp, 0.0, -turn);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyZ)) {
+        dolly_camera(vp, 0.02);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyX)) {
+        dolly_camera(vp, -0.02);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyC)) {
+        set_controller(vp, CAMERA_ORBIT);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyV)) {
+        set_controller(vp, CAMERA_FREE_FLY);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyB)) {
+        // Ground heights come from the startup analysis
+        match &state.terrain_analysis {
+            Some(analysis) if vp.controller != CAMERA_SURFACE => {
+                enter_surface_mode(vp, &analysis.height_field, time);
+            }
+            Some(_) => {}
+            None => println!(
+                "Surface camera needs the heightmap ({})",
+                state.startup.phase().label()
+            ),
+        }
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::Minus)) {
+        vp.fov_degrees = f32::min(90.0, vp.fov_degrees + 0.5);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::Equal)) {
+        vp.fov_degrees = f32::max(2.0, vp.fov_degrees - 0.5);
+    // RAY MARCH ACCELERATION -------------------------------------------------------
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyA)) {
+        state.params.ray_params.accelerate = 1.0;
+        update_ray_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyN)) {
+        state.params.ray_params.accelerate = 0.0;
+        update_ray_params_buffer(state);
+    // SPHERE TRACING ---------------------------------------------------------------
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyR)) {
+        state.params.ray_params.relaxation =
+            f32::min(2.0, state.params.ray_params.relaxation + 0.05);
+        update_ray_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyF)) {
+        state.params.ray_params.relaxation =
+            f32::max(1.0, state.params.ray_params.relaxation - 0.05);
+        update_ray_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyG)) {
+        state.params.ray_params.gradient_bound += 0.05;
+        update_ray_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyH)) {
+        state.params.ray_params.gradient_bound =
+            f32::max(1.0, state.params.ray_params.gradient_bound - 0.05);
+        update_ray_params_buffer(state);
+    }
+}
+
+fn water_controls(state: &mut State) {
+    let pressed = state.controls.get_keys();
+    let shift = pressed.contains(&PhysicalKey::Code(KeyCode::ShiftLeft));
+    let wp = &mut state.params.water_params;
 
-    if pressed.contains(&PhysicalKey::Code(KeyCode::ArrowLeft)) {
-        if pressed.contains(&PhysicalKey::Code(KeyCode::ShiftLeft)) {
-            state.params.view_params.x_rot = f32::max(0.0, state.params.view_params.x_rot + 0.1);
-            update_view_params_buffer(state);
+    if pressed.contains(&PhysicalKey::Code(KeyCode::ArrowUp)) {
+        if shift {
+            wp.wave_frequency *= 1.02;
+        } else {
+            wp.wave_amplitude *= 1.02;
+        }
+        update_water_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::ArrowDown)) {
+        if shift {
+            wp.wave_frequency = f32::max(1.0, wp.wave_frequency / 1.02);
         } else {
-            state.params.view_params.x_shift -= 0.01 / mz;
-            update_view_params_buffer(state);
+            wp.wave_amplitude = f32::max(0.001, wp.wave_amplitude / 1.02);
         }
+        update_water_params_buffer(state);
     } else if pressed.contains(&PhysicalKey::Code(KeyCode::ArrowRight)) {
-        if pressed.contains(&PhysicalKey::Code(KeyCode::ShiftLeft)) {
-            state.params.view_params.x_rot -= 0.1;
-            update_view_params_buffer(state);
+        if shift {
+            wp.shoaling += 0.05;
         } else {
-            state.params.view_params.x_shift += 0.01 / mz;
-            update_view_params_buffer(state);
+            wp.wave_speed *= 1.02;
+        }
+        update_water_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::ArrowLeft)) {
+        if shift {
+            wp.shoaling = f32::max(0.0, wp.shoaling - 0.05);
+        } else {
+            wp.wave_speed = f32::max(0.0001, wp.wave_speed / 1.02);
+        }
+        update_water_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyX)) {
+        wp.foam_width = f32::max(0.0, wp.foam_width - 0.005);
+        update_water_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyZ)) {
+        wp.foam_width += 0.005;
+        update_water_params_buffer(state);
+    }
+}
+
+fn atmosphere_controls(state: &mut State) {
+    let pressed = state.controls.get_keys();
+    let shift = pressed.contains(&PhysicalKey::Code(KeyCode::ShiftLeft));
+    let ap = &mut state.params.atmosphere_params;
+
+    // Hold R, G or B with Up/Down to tune a single wavelength
+    let channel = if pressed.contains(&PhysicalKey::Code(KeyCode::KeyR)) {
+        Some(&mut ap.rayleigh_r)
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyG)) {
+        Some(&mut ap.rayleigh_g)
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyB)) {
+        Some(&mut ap.rayleigh_b)
+    } else {
+        None
+    };
+
+    if let Some(coefficient) = channel {
+        if pressed.contains(&PhysicalKey::Code(KeyCode::ArrowUp)) {
+            *coefficient *= 1.02;
+            update_atmosphere_params_buffer(state);
+        } else if pressed.contains(&PhysicalKey::Code(KeyCode::ArrowDown)) {
+            *coefficient = f32::max(0.0001, *coefficient / 1.02);
+            update_atmosphere_params_buffer(state);
         }
     } else if pressed.contains(&PhysicalKey::Code(KeyCode::ArrowUp)) {
-        if pressed.contains(&PhysicalKey::Code(KeyCode::ShiftLeft)) {
-            state.params.view_params.y_rot = f32::max(0.0, state.params.view_params.y_rot + 0.1);
-            update_view_params_buffer(state);
+        if shift {
+            ap.outer_radius += 0.05;
         } else {
-            state.params.view_params.y_shift -= 0.01 / mz;
-            update_view_params_buffer(state);
+            ap.density *= 1.02;
         }
+        update_atmosphere_params_buffer(state);
     } else if pressed.contains(&PhysicalKey::Code(KeyCode::ArrowDown)) {
-        if pressed.contains(&PhysicalKey::Code(KeyCode::ShiftLeft)) {
-            state.params.view_params.y_rot -= 0.1;
-            update_view_params_buffer(state);
+        if shift {
+            ap.outer_radius = f32::max(PLANET_RADIUS + 0.5, ap.outer_radius - 0.05);
+        } else {
+            ap.density = f32::max(0.0, ap.density / 1.02);
+        }
+        update_atmosphere_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::ArrowRight)) {
+        if shift {
+            ap.mie_scale_height *= 1.02;
         } else {
-            state.params.view_params.y_shift += 0.01 / mz;
-            update_view_params_buffer(state);
+            ap.rayleigh_scale_height *= 1.02;
         }
+        update_atmosphere_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::ArrowLeft)) {
+        if shift {
+            ap.mie_scale_height = f32::max(0.01, ap.mie_scale_height / 1.02);
+        } else {
+            ap.rayleigh_scale_height = f32::max(0.01, ap.rayleigh_scale_height / 1.02);
+        }
+        update_atmosphere_params_buffer(state);
     } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyX)) {
-        state.params.view_params.zoom -= 0.1 * mz;
-        update_view_params_buffer(state);
+        ap.mie_g = f32::max(0.0, ap.mie_g - 0.005);
+        update_atmosphere_params_buffer(state);
     } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyZ)) {
-        state.params.view_params.zoom += 0.1 * mz;
-        update_view_params_buffer(state);
+        ap.mie_g = f32::min(0.99, ap.mie_g + 0.005);
+        update_atmosphere_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyV)) {
+        ap.sun_intensity = f32::max(0.0, ap.sun_intensity / 1.02);
+        update_atmosphere_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyC)) {
+        ap.sun_intensity *= 1.02;
+        update_atmosphere_params_buffer(state);
+    }
+}
+
+fn cloud_controls(state: &mut State) {
+    let pressed = state.controls.get_keys();
+    let shift = pressed.contains(&PhysicalKey::Code(KeyCode::ShiftLeft));
+    let cp = &mut state.params.cloud_params;
+
+    if pressed.contains(&PhysicalKey::Code(KeyCode::KeyC)) {
+        cp.enabled = 1.0;
+        update_cloud_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyV)) {
+        cp.enabled = 0.0;
+        update_cloud_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::ArrowUp)) {
+        if shift {
+            cp.altitude += 0.02;
+        } else {
+            cp.coverage = f32::min(1.0, cp.coverage + 0.005);
+        }
+        update_cloud_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::ArrowDown)) {
+        if shift {
+            cp.altitude = f32::max(0.0, cp.altitude - 0.02);
+        } else {
+            cp.coverage = f32::max(0.0, cp.coverage - 0.005);
+        }
+        update_cloud_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::ArrowRight)) {
+        if shift {
+            cp.thickness += 0.02;
+        } else {
+            cp.wind_speed += 0.001;
+        }
+        update_cloud_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::ArrowLeft)) {
+        if shift {
+            cp.thickness = f32::max(0.05, cp.thickness - 0.02);
+        } else {
+            cp.wind_speed -= 0.001;
+        }
+        update_cloud_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyX)) {
+        cp.density = f32::max(0.0, cp.density / 1.02);
+        update_cloud_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyZ)) {
+        cp.density *= 1.02;
+        update_cloud_params_buffer(state);
+    }
+}
+
+fn ring_controls(state: &mut State) {
+    let pressed = state.controls.get_keys();
+    let shift = pressed.contains(&PhysicalKey::Code(KeyCode::ShiftLeft));
+    let rp = &mut state.params.ring_params;
+
+    if pressed.contains(&PhysicalKey::Code(KeyCode::KeyC)) {
+        rp.enabled = 1.0;
+        update_ring_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyV)) {
+        rp.enabled = 0.0;
+        update_ring_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::ArrowUp)) {
+        // Shift moves the inner edge, the rings never get narrower than 0.5
+        if shift {
+            rp.inner_radius = f32::min(rp.outer_radius - 0.5, rp.inner_radius + 0.05);
+        } else {
+            rp.outer_radius += 0.05;
+        }
+        update_ring_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::ArrowDown)) {
+        if shift {
+            rp.inner_radius = f32::max(PLANET_RADIUS, rp.inner_radius - 0.05);
+        } else {
+            rp.outer_radius = f32::max(rp.inner_radius + 0.5, rp.outer_radius - 0.05);
+        }
+        update_ring_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::ArrowRight)) {
+        rp.tilt = f32::min(std::f32::consts::FRAC_PI_2, rp.tilt + 0.005);
+        update_ring_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::ArrowLeft)) {
+        rp.tilt = f32::max(-std::f32::consts::FRAC_PI_2, rp.tilt - 0.005);
+        update_ring_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyZ)) {
+        rp.opacity = f32::min(1.0, rp.opacity + 0.005);
+        update_ring_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyX)) {
+        rp.opacity = f32::max(0.0, rp.opacity - 0.005);
+        update_ring_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyB)) {
+        rp.band_frequency *= 1.01;
+        update_ring_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyN)) {
+        rp.band_frequency = f32::max(1.0, rp.band_frequency / 1.01);
+        update_ring_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyG)) {
+        rp.scatter_g = f32::min(0.95, rp.scatter_g + 0.005);
+        update_ring_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyH)) {
+        rp.scatter_g = f32::max(0.0, rp.scatter_g - 0.005);
+        update_ring_params_buffer(state);
+    }
+}
+
+fn light_controls(state: &mut State) {
+    let pressed = state.controls.get_keys();
+    let shift = pressed.contains(&PhysicalKey::Code(KeyCode::ShiftLeft));
+    let lp = &mut state.params.light_params;
+
+    // Shift moves the fill light instead of the sun
+    let (azimuth, elevation, intensity) = if shift {
+        (
+            &mut lp.fill_azimuth,
+            &mut lp.fill_elevation,
+            &mut lp.fill_intensity,
+        )
+    } else {
+        (
+            &mut lp.sun_azimuth,
+            &mut lp.sun_elevation,
+            &mut lp.sun_intensity,
+        )
+    };
+
+    if pressed.contains(&PhysicalKey::Code(KeyCode::ArrowLeft)) {
+        *azimuth -= 0.02;
+        update_light_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::ArrowRight)) {
+        *azimuth += 0.02;
+        update_light_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::ArrowUp)) {
+        *elevation = f32::min(std::f32::consts::FRAC_PI_2, *elevation + 0.02);
+        update_light_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::ArrowDown)) {
+        *elevation = f32::max(-std::f32::consts::FRAC_PI_2, *elevation - 0.02);
+        update_light_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyC)) {
+        *intensity *= 1.02;
+        update_light_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyV)) {
+        *intensity = f32::max(0.0, *intensity / 1.02);
+        update_light_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyZ)) {
+        lp.orbit_speed += 0.002;
+        update_light_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyX)) {
+        lp.orbit_speed -= 0.002;
+        update_light_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyN)) {
+        lp.angular_radius *= 1.02;
+        update_light_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyM)) {
+        lp.angular_radius = f32::max(0.001, lp.angular_radius / 1.02);
+        update_light_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyT)) {
+        // Warmer
+        lp.sun_r = f32::min(1.0, lp.sun_r + 0.005);
+        lp.sun_b = f32::max(0.0, lp.sun_b - 0.005);
+        update_light_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyY)) {
+        // Cooler
+        lp.sun_r = f32::max(0.0, lp.sun_r - 0.005);
+        lp.sun_b = f32::min(1.0, lp.sun_b + 0.005);
+        update_light_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyF)) {
+        lp.fill_enabled = 1.0;
+        update_light_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyG)) {
+        lp.fill_enabled = 0.0;
+        update_light_params_buffer(state);
+    }
+}
+
+fn background_controls(state: &mut State) {
+    let pressed = state.controls.get_keys();
+    let shift = pressed.contains(&PhysicalKey::Code(KeyCode::ShiftLeft));
+    let bp = &mut state.params.background_params;
+
+    if pressed.contains(&PhysicalKey::Code(KeyCode::KeyC)) {
+        bp.nebula_enabled = 1.0;
+        update_background_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyV)) {
+        bp.nebula_enabled = 0.0;
+        update_background_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::ArrowUp)) {
+        if shift {
+            bp.nebula_intensity *= 1.02;
+        } else {
+            bp.star_brightness *= 1.02;
+        }
+        update_background_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::ArrowDown)) {
+        if shift {
+            bp.nebula_intensity /= 1.02;
+        } else {
+            bp.star_brightness /= 1.02;
+        }
+        update_background_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::ArrowRight)) {
+        if shift {
+            bp.env_rotation += 0.02;
+        } else {
+            bp.star_density = f32::min(1.0, bp.star_density + 0.002);
+        }
+        update_background_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::ArrowLeft)) {
+        if shift {
+            bp.env_rotation -= 0.02;
+        } else {
+            bp.star_density = f32::max(0.0, bp.star_density - 0.002);
+        }
+        update_background_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyZ)) {
+        bp.sun_disc_intensity *= 1.02;
+        update_background_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyX)) {
+        bp.sun_disc_intensity /= 1.02;
+        update_background_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyN)) {
+        bp.env_intensity *= 1.02;
+        update_background_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyM)) {
+        bp.env_intensity /= 1.02;
+        update_background_params_buffer(state);
+    }
+}
+
+fn post_controls(state: &mut State) {
+    let pressed = state.controls.get_keys();
+    let shift = pressed.contains(&PhysicalKey::Code(KeyCode::ShiftLeft));
+    let pp = &mut state.params.post_params;
+
+    // TONEMAPPER -----------------------------------------------------------------
+    if pressed.contains(&PhysicalKey::Code(KeyCode::KeyZ)) {
+        pp.tonemapper = 0.0;
+        update_post_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyX)) {
+        pp.tonemapper = 1.0;
+        update_post_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyC)) {
+        pp.tonemapper = 2.0;
+        update_post_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyV)) {
+        pp.tonemapper = 3.0;
+        update_post_params_buffer(state);
+    // STAGE TOGGLES --------------------------------------------------------------
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyE)) {
+        pp.exposure_enabled = 1.0;
+        update_post_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyR)) {
+        pp.exposure_enabled = 0.0;
+        update_post_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyB)) {
+        pp.bloom_enabled = 1.0;
+        update_post_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyN)) {
+        pp.bloom_enabled = 0.0;
+        update_post_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyS)) {
+        pp.srgb_enabled = 1.0;
+        update_post_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyF)) {
+        pp.srgb_enabled = 0.0;
+        update_post_params_buffer(state);
+    // VALUES ---------------------------------------------------------------------
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::ArrowUp)) {
+        pp.exposure += 0.02;
+        update_post_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::ArrowDown)) {
+        pp.exposure -= 0.02;
+        update_post_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::ArrowRight)) {
+        if shift {
+            pp.bloom_threshold += 0.01;
+        } else {
+            pp.bloom_intensity += 0.005;
+        }
+        update_post_params_buffer(state);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::ArrowLeft)) {
+        if shift {
+            pp.bloom_threshold = f32::max(0.0, pp.bloom_threshold - 0.01);
+        } else {
+            pp.bloom_intensity = f32::max(0.0, pp.bloom_intensity - 0.005);
+        }
+        update_post_params_buffer(state);
+    }
+}
+
+fn antialias_controls(state: &mut State) {
+    let pressed = state.controls.get_keys();
+    let aa = &mut state.params.aa_params;
+
+    // The per frame fields are written by State::update_anti_aliasing
+    if pressed.contains(&PhysicalKey::Code(KeyCode::KeyZ)) {
+        aa.mode = AA_OFF;
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyX)) {
+        aa.mode = AA_SSAA;
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyC)) {
+        aa.mode = AA_TAA;
+    // SSAA QUALITY, N * N rays per pixel ------------------------------------------
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyQ)) {
+        aa.ssaa_samples = 1.0;
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyW)) {
+        aa.ssaa_samples = 2.0;
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyE)) {
+        aa.ssaa_samples = 3.0;
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyR)) {
+        aa.ssaa_samples = 4.0;
+    // TAA HISTORY WEIGHT ----------------------------------------------------------
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::ArrowUp)) {
+        aa.taa_blend = f32::min(0.98, aa.taa_blend + 0.002);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::ArrowDown)) {
+        aa.taa_blend = f32::max(0.0, aa.taa_blend - 0.002);
+    }
+}
+
+// Every action here happens once, then control returns to the camera
+fn path_controls(state: &mut State) {
+    let pressed = state.controls.get_keys();
+
+    if pressed.contains(&PhysicalKey::Code(KeyCode::KeyK)) {
+        state.add_keyframe();
+        state.controls.set_mode(KeyboardMode::VIEW);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyJ)) {
+        state.remove_keyframe();
+        state.controls.set_mode(KeyboardMode::VIEW);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyG)) {
+        state.play_camera_path();
+        state.controls.set_mode(KeyboardMode::VIEW);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyH)) {
+        state.stop_camera_path();
+        state.controls.set_mode(KeyboardMode::VIEW);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyS)) {
+        match save_camera_path(&state.camera_path, Path::new(SCREENSHOT_DIR)) {
+            Ok(path) => println!("Saved camera path to {}", path.display()),
+            Err(e) => eprintln!("Error saving camera path: {:?}", e),
+        }
+        state.controls.set_mode(KeyboardMode::VIEW);
+    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyL)) {
+        match latest_camera_path(Path::new(SCREENSHOT_DIR)) {
+            Ok(Some(path)) => match state.load_camera_path(&path) {
+                Ok(seed_changed) => {
+                    println!("Loaded camera path from {}", path.display());
+                    if seed_changed {
+                        state.regenerate_terrain();
+                    }
+                }
+                Err(e) => eprintln!("Error loading camera path: {:?}", e),
+            },
+            Ok(None) => println!("No saved camera paths in {}", SCREENSHOT_DIR),
+            Err(e) => eprintln!("Error loading camera path: {:?}", e),
+        }
+        state.controls.set_mode(KeyboardMode::VIEW);
     }
 }
 
@@ -314,7 +875,47 @@ fn print_controls(state: &mut State) {
     println!("\n{:#?}", state.params.terrain_params);
     println!("\n{:#?}", state.params.view_params);
     println!("\n{:#?}", state.params.ray_params);
+    println!("\n{:#?}", state.params.water_params);
+    println!("\n{:#?}", state.params.atmosphere_params);
+    println!("\n{:#?}", state.params.cloud_params);
+    println!("\n{:#?}", state.params.ring_params);
+    println!("\n{:#?}", state.params.light_params);
+    println!("\n{:#?}", state.params.background_params);
+    println!("\n{:#?}", state.params.post_params);
+    println!("\n{:#?}", state.params.aa_params);
     println!("\n{:#?}", state.params.debug_params);
+
+    // Shift + P also writes them to a params file, a plain print pass saves nothing
+    if state
+        .controls
+        .key_pressed(PhysicalKey::Code(KeyCode::ShiftLeft))
+    {
+        match save_params(&state.params, Path::new(SCREENSHOT_DIR)) {
+            Ok(path) => println!("Saved parameters to {}", path.display()),
+            Err(e) => eprintln!("Error saving parameters: {:?}", e),
+        }
+    }
+
+    // TERRAIN STATISTICS ----------------------------------------------------------
+    // Ctrl + P exports the terrain report next to them
+    if state
+        .controls
+        .key_pressed(PhysicalKey::Code(KeyCode::ControlLeft))
+    {
+        state.export_terrain_report();
+    }
+
+    // UPCOMING ECLIPSES -----------------------------------------------------------
+    let now = state.get_time();
+    let forecast = 2.0 * std::f32::consts::TAU / MOON_ORBIT_SPEED;
+    println!("\nEclipses in the next {:.0}s:", forecast);
+    for eclipse in predict_eclipses(&state.params.light_params, now, forecast) {
+        println!(
+            "  {:?}: {:.2}s -> {:.2}s, peak {:.2}s (magnitude {:.2})",
+            eclipse.kind, eclipse.start, eclipse.end, eclipse.peak, eclipse.magnitude
+        );
+    }
+
     println!("------------------------------------------------------\n");
     state.controls.mode = KeyboardMode::VIEW;
 }
@@ -1,2 +1,3 @@
 pub(crate) mod app_state;
 pub(crate) mod control_state;
+pub(crate) mod startup_state;
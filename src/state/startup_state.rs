@@ -0,0 +1,125 @@
+use std::sync::mpsc;
+use std::time::Instant;
+
+use crate::{
+    analysis::{height_field::HeightField, terrain_report::TerrainReport},
+    readback::readback_queue::ReadbackError,
+};
+
+// CPU-side results of the startup terrain analysis
+#[derive(Debug)]
+pub(crate) struct TerrainAnalysis {
+    pub(crate) report: TerrainReport,
+    // Ground height lookups for the surface camera
+    pub(crate) height_field: HeightField,
+}
+
+#[derive(Debug)]
+pub(crate) enum StartupEvent {
+    GpuWorkDone,
+    HeightMapRead,
+    HeightMapFailed(ReadbackError),
+    AnalysisDone(Box<TerrainAnalysis>),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) enum StartupPhase {
+    // Terrain textures and wave directions, all on the GPU
+    Generating,
+    ReadingBack,
+    Analysing,
+    Done,
+}
+
+impl StartupPhase {
+    pub(crate) fn label(&self) -> &'static str {
+        match self {
+            StartupPhase::Generating => "Generating terrain",
+            StartupPhase::ReadingBack => "Reading back heightmap",
+            StartupPhase::Analysing => "Analysing terrain",
+            StartupPhase::Done => "Done",
+        }
+    }
+}
+
+// Tracks the startup work that runs alongside the first frames. Events come
+// from GPU callbacks and the analysis worker thread.
+#[derive(Debug)]
+pub(crate) struct StartupState {
+    phase: StartupPhase,
+    started: Instant,
+    phase_started: Instant,
+    tx: mpsc::Sender<StartupEvent>,
+    rx: mpsc::Receiver<StartupEvent>,
+}
+
+impl StartupState {
+    pub(crate) fn new() -> Self {
+        let (tx, rx) = mpsc::channel();
+        let now = Instant::now();
+
+        Self {
+            phase: StartupPhase::Generating,
+            started: now,
+            phase_started: now,
+            tx,
+            rx,
+        }
+    }
+
+    pub(crate) fn sender(&self) -> mpsc::Sender<StartupEvent> {
+        self.tx.clone()
+    }
+
+    pub(crate) fn phase(&self) -> StartupPhase {
+        self.phase
+    }
+
+    // Work from the previous run may still be in flight, its senders keep the old
+    // channel and their events are dropped with it instead of advancing this run
+    pub(crate) fn restart(&mut self) {
+        (self.tx, self.rx) = mpsc::channel();
+
+        let now = Instant::now();
+        self.phase = StartupPhase::Generating;
+        self.started = now;
+        self.phase_started = now;
+        log::info!("{}...", self.phase.label());
+    }
+
+    // Returns the analysis once the worker thread has finished, never blocks
+    pub(crate) fn poll(&mut self) -> Option<TerrainAnalysis> {
+        while let Ok(event) = self.rx.try_recv() {
+            match event {
+                StartupEvent::GpuWorkDone => self.advance(StartupPhase::ReadingBack),
+                StartupEvent::HeightMapRead => self.advance(StartupPhase::Analysing),
+                StartupEvent::HeightMapFailed(e) => {
+                    log::error!("Heightmap readback failed, skipping analysis: {:?}", e);
+                    self.advance(StartupPhase::Done);
+                }
+                StartupEvent::AnalysisDone(analysis) => {
+                    self.advance(StartupPhase::Done);
+                    log::info!("Startup complete in {:.2?}", self.started.elapsed());
+                    return Some(*analysis);
+                }
+            }
+        }
+
+        None
+    }
+
+    fn advance(&mut self, next: StartupPhase) {
+        log::info!(
+            "{} took {:.2?}",
+            self.phase.label(),
+            self.phase_started.elapsed()
+        );
+
+        self.phase = next;
+        self.phase_started = Instant::now();
+
+        if next != StartupPhase::Done {
+            log::info!("{}...", next.label());
+        }
+    }
+}
@@ -1,69 +1,99 @@
-use crate::{
-    collections::structs::{DebugParams, ViewParams},
-    state::app_state::State,
-};
+use crate::{camera::camera_controller::sync_camera, state::app_state::State};
 
-pub(crate) fn update_view_params_buffer(state: &mut State) {
-    let new_view_params = ViewParams {
-        x_shift: state.params.view_params.x_shift,
-        y_shift: state.params.view_params.y_shift,
-        zoom: state.params.view_params.zoom,
-        x_rot: state.params.view_params.x_rot,
-        y_rot: state.params.view_params.y_rot,
-        time_modifier: state.params.view_params.time_modifier,
-        fov_degrees: state.params.view_params.fov_degrees,
-    };
+pub(crate) fn update_terrain_params_buffer(state: &mut State) {
+    state.queue.write_buffer(
+        &state.buffers.terrain_params,
+        0,
+        bytemuck::cast_slice(&[state.params.terrain_params]),
+    );
+}
+
+pub(crate) fn update_camera_params_buffer(state: &mut State) {
+    let camera = sync_camera(&mut state.params.view_params).params(&state.params.view_params);
 
     state.queue.write_buffer(
-        &state.buffers.view_params,
+        &state.buffers.camera_params,
         0,
-        bytemuck::cast_slice(&[new_view_params]),
+        bytemuck::cast_slice(&[camera]),
     );
 }
 
-pub(crate) fn update_debug_params_buffer(state: &mut State) {
-    let new_debug_params = DebugParams {
-        pole_start: state.params.debug_params.pole_start,
-        pole_scale: state.params.debug_params.pole_scale,
-    };
+pub(crate) fn update_water_params_buffer(state: &mut State) {
+    state.queue.write_buffer(
+        &state.buffers.water_params,
+        0,
+        bytemuck::cast_slice(&[state.params.water_params]),
+    );
+}
 
+pub(crate) fn update_atmosphere_params_buffer(state: &mut State) {
     state.queue.write_buffer(
-        &state.buffers.debug_params,
+        &state.buffers.atmosphere_params,
         0,
-        bytemuck::cast_slice(&[new_debug_params]),
+        bytemuck::cast_slice(&[state.params.atmosphere_params]),
     );
 }
 
-pub(crate) fn update_cpu_read_buffers(state: &mut State) {
-    let mut encoder = state
-        .device
-        .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-            label: Some("update_cpu_read_buffers encoder"),
-        });
+pub(crate) fn update_cloud_params_buffer(state: &mut State) {
+    state.queue.write_buffer(
+        &state.buffers.cloud_params,
+        0,
+        bytemuck::cast_slice(&[state.params.cloud_params]),
+    );
+}
 
-    encoder.copy_buffer_to_buffer(
-        &state.buffers.generic_debug,
+pub(crate) fn update_light_params_buffer(state: &mut State) {
+    state.queue.write_buffer(
+        &state.buffers.light_params,
         0,
-        &state.buffers.cpu_read_generic_debug,
+        bytemuck::cast_slice(&[state.params.light_params]),
+    );
+}
+
+pub(crate) fn update_background_params_buffer(state: &mut State) {
+    state.queue.write_buffer(
+        &state.buffers.background_params,
         0,
-        (std::mem::size_of::<[f32; 4]>()) as wgpu::BufferAddress,
+        bytemuck::cast_slice(&[state.params.background_params]),
     );
+}
 
-    encoder.copy_buffer_to_buffer(
-        &state.buffers.debug_array1,
+pub(crate) fn update_ring_params_buffer(state: &mut State) {
+    state.queue.write_buffer(
+        &state.buffers.ring_params,
         0,
-        &state.buffers.cpu_read_debug_array1,
+        bytemuck::cast_slice(&[state.params.ring_params]),
+    );
+}
+
+pub(crate) fn update_post_params_buffer(state: &mut State) {
+    state.queue.write_buffer(
+        &state.buffers.post_params,
         0,
-        (std::mem::size_of::<[[f32; 4]; 512]>()) as wgpu::BufferAddress,
+        bytemuck::cast_slice(&[state.params.post_params]),
     );
+}
 
-    encoder.copy_buffer_to_buffer(
-        &state.buffers.debug_array2,
+pub(crate) fn update_ray_params_buffer(state: &mut State) {
+    state.queue.write_buffer(
+        &state.buffers.ray_params,
         0,
-        &state.buffers.cpu_read_debug_array2,
+        bytemuck::cast_slice(&[state.params.ray_params]),
+    );
+}
+
+pub(crate) fn update_aa_params_buffer(state: &mut State) {
+    state.queue.write_buffer(
+        &state.buffers.aa_params,
         0,
-        (std::mem::size_of::<[[f32; 4]; 512]>()) as wgpu::BufferAddress,
+        bytemuck::cast_slice(&[state.params.aa_params]),
     );
+}
 
-    state.queue.submit(Some(encoder.finish()));
+pub(crate) fn update_debug_params_buffer(state: &mut State) {
+    state.queue.write_buffer(
+        &state.buffers.debug_params,
+        0,
+        bytemuck::cast_slice(&[state.params.debug_params]),
+    );
 }